@@ -1,23 +1,299 @@
+use crate::baseline::Baseline;
+use crate::graph::CallGraph;
+use crate::metrics::Metrics;
+use crate::policy::AllowRule;
+use crate::system_api;
 use crate::utils::create_bar;
 use crate::utils::{crate_name, display_path};
 use console::style;
 use fxhash::FxHashSet;
 use indicatif::MultiProgress;
-use log::{error, info, trace};
+use log::{error, info, trace, warn};
 use ra_ap_base_db::CrateId;
 use ra_ap_hir::{self as hir, Crate, HirDisplay, Semantics};
 use ra_ap_hir_def::FunctionId;
 use ra_ap_ide::RootDatabase;
 use ra_ap_syntax::SyntaxNode;
+use ra_ap_vfs::Vfs;
+use serde::{Deserialize, Serialize};
 
 #[derive(PartialEq)]
 pub enum Mode {
     TraceFunctions,
+    /// Trace from canister entry points only, reporting findings per entry point
+    EntryPoints,
     ScanExports,
 }
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    /// Colored messages for terminal
+    Human,
+    /// One JSON record per finding
+    Json,
+    /// SARIF 2.1 log for code review tools
+    Sarif,
+}
+/// Serialized as on the command line, e.g., `invalid-import`
+#[derive(
+    Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    Import,
+    /// Import that is not part of the System API, or has the wrong signature
+    InvalidImport,
+    Export,
+    /// Static placed in a custom wasm section via `#[link_section]`
+    CustomSection,
+    /// Global mutable state, which is wiped on upgrade unless persisted explicitly
+    GlobalState,
+    /// `RefCell` borrow or lock guard held across an `.await` in an update method
+    BorrowAcrossAwait,
+    /// `unwrap`, `expect`, `panic!`, indexing or integer overflow that traps the canister
+    Panic,
+    /// Non-deterministic or wasm-unsupported API, such as `SystemTime::now` or `std::fs`
+    UnsupportedApi,
+    /// Outbound inter-canister call or cycles transfer
+    CanisterCall,
+    /// Process, network or filesystem access in build scripts and proc-macro crates
+    BuildTime,
+    Unsafe,
+}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Location {
+    /// Absolute path, until it is made relative for output
+    pub file: String,
+    /// One-based line and column
+    pub line: u32,
+    pub column: u32,
+    pub end_line: u32,
+    pub end_column: u32,
+}
+impl Location {
+    /// Relative to the current directory, if the file is under it
+    pub fn relativize(&mut self, cwd: &std::path::Path) {
+        if let Ok(path) = std::path::Path::new(&self.file).strip_prefix(cwd) {
+            self.file = path.display().to_string();
+        }
+    }
+}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Finding {
+    pub kind: Kind,
+    /// Path of the function, or the static for `CustomSection` and `GlobalState`
+    pub function: String,
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub version: Option<String>,
+    pub whitelisted: bool,
+    /// Canister entry point that reaches the finding, e.g., "query `greet`"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    /// Justification from the matching allow rule in the policy file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub justification: Option<String>,
+    /// Kind specific information, e.g., the exported name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// Call path from the root function
+    pub path: Vec<String>,
+    pub location: Option<Location>,
+    /// Target triple, when the finding only exists under one target in `--all-targets` mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+impl Finding {
+    /// Whitelisted or allowed by the policy file
+    pub fn is_suppressed(&self) -> bool {
+        self.whitelisted || self.justification.is_some()
+    }
+    /// Message without terminal styling
+    pub fn text(&self) -> String {
+        self.with_target(self.kind_text())
+    }
+    fn with_target(&self, msg: String) -> String {
+        match &self.target {
+            Some(target) => format!("{msg} (only on {target})"),
+            None => msg,
+        }
+    }
+    fn kind_text(&self) -> String {
+        let detail = self.detail.as_deref().unwrap_or_default();
+        if let Some(endpoint) = &self.endpoint {
+            return match self.kind {
+                Kind::Import => format!("{endpoint} reaches import {} of {detail}", self.function),
+                Kind::InvalidImport => {
+                    format!(
+                        "{endpoint} reaches invalid import {}: {detail}",
+                        self.function
+                    )
+                }
+                Kind::Export => format!("{endpoint} reaches export {detail} in {}", self.function),
+                Kind::CustomSection => {
+                    format!(
+                        "{endpoint} reaches custom section {detail} in {}",
+                        self.function
+                    )
+                }
+                Kind::GlobalState => {
+                    format!(
+                        "{endpoint} reaches global state {} ({detail})",
+                        self.function
+                    )
+                }
+                Kind::BorrowAcrossAwait => format!("{endpoint} holds {detail}"),
+                Kind::Panic => format!("{endpoint} may panic at {detail} in {}", self.function),
+                Kind::UnsupportedApi => {
+                    format!("{endpoint} calls unsupported {detail} in {}", self.function)
+                }
+                Kind::CanisterCall => format!("{endpoint} makes {detail} in {}", self.function),
+                Kind::BuildTime => format!("{} {detail}", self.function),
+                Kind::Unsafe => format!("{endpoint} reaches unsafe in {}", self.function),
+            };
+        }
+        match self.kind {
+            Kind::Import => format!("{} is an external import of {detail}", self.function),
+            Kind::InvalidImport => format!("{} is an invalid import: {detail}", self.function),
+            Kind::Export => format!("{} exports {detail}", self.function),
+            Kind::CustomSection => {
+                format!("{} is placed in custom section {detail}", self.function)
+            }
+            Kind::GlobalState => format!("{} is global state ({detail})", self.function),
+            Kind::BorrowAcrossAwait => format!("{} holds {detail}", self.function),
+            Kind::Panic => format!("{} may panic at {detail}", self.function),
+            Kind::UnsupportedApi => format!("{} calls unsupported {detail}", self.function),
+            Kind::CanisterCall => format!("{} makes {detail}", self.function),
+            Kind::BuildTime => format!("{} {detail}", self.function),
+            Kind::Unsafe => format!("{} contains unsafe blocks", self.function),
+        }
+    }
+    pub fn message(&self) -> String {
+        let msg = match self.kind {
+            Kind::Import => format!(
+                "{} {} is an {} import of {}!",
+                style("[Import]").red().bold(),
+                style(&self.function).red(),
+                style("external").red(),
+                style(self.detail.as_deref().unwrap_or_default()).red()
+            ),
+            Kind::InvalidImport => format!(
+                "{} {} {}",
+                style("[Invalid]").red().bold(),
+                style(&self.function).red(),
+                self.detail.as_deref().unwrap_or_default()
+            ),
+            Kind::Export => format!(
+                "{} {} exports {}",
+                style("[Export]").yellow().bold(),
+                style(&self.function).yellow(),
+                style(self.detail.as_deref().unwrap_or_default()).yellow()
+            ),
+            Kind::CustomSection => format!(
+                "{} {} is placed in custom section {}",
+                style("[Section]").yellow().bold(),
+                style(&self.function).yellow(),
+                style(self.detail.as_deref().unwrap_or_default()).yellow()
+            ),
+            Kind::GlobalState => format!(
+                "{} {} is {} ({})",
+                style("[State]").yellow().bold(),
+                style(&self.function).yellow(),
+                style("global state").yellow(),
+                self.detail.as_deref().unwrap_or_default()
+            ),
+            Kind::BorrowAcrossAwait => format!(
+                "{} {} holds {}",
+                style("[Borrow]").red().bold(),
+                style(&self.function).red(),
+                style(self.detail.as_deref().unwrap_or_default()).red()
+            ),
+            Kind::Panic => format!(
+                "{} {} may {} at {}",
+                style("[Panic]").yellow().bold(),
+                style(&self.function).yellow(),
+                style("panic").yellow(),
+                self.detail.as_deref().unwrap_or_default()
+            ),
+            Kind::UnsupportedApi => format!(
+                "{} {} calls {} {}",
+                style("[Api]").red().bold(),
+                style(&self.function).red(),
+                style("unsupported").red(),
+                self.detail.as_deref().unwrap_or_default()
+            ),
+            Kind::CanisterCall => format!(
+                "{} {} makes {}",
+                style("[Call]").cyan().bold(),
+                style(&self.function).cyan(),
+                self.detail.as_deref().unwrap_or_default()
+            ),
+            Kind::BuildTime => format!(
+                "{} {} {}",
+                style("[Build]").red().bold(),
+                style(&self.function).red(),
+                self.detail.as_deref().unwrap_or_default()
+            ),
+            Kind::Unsafe => format!(
+                "{} {} contains {} blocks!",
+                style("[Unsafe]").yellow().bold(),
+                style(&self.function).yellow(),
+                style("unsafe").yellow()
+            ),
+        };
+        self.with_target(msg)
+    }
+}
+#[derive(Serialize)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+    /// Build scripts and proc-macro crates in `--build-time` mode
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<crate::build_time::Target>,
+    /// Per-crate footprint of the audited functions
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub crates: Vec<crate::metrics::CrateMetrics>,
+}
+/// Report or diff of one canister, when canisters are selected with `--workspace` or `--package`
+#[derive(Serialize)]
+pub struct Section<T> {
+    pub canister: String,
+    #[serde(flatten)]
+    pub report: T,
+}
 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Output {
+    pub visited: usize,
+    pub findings: Vec<Finding>,
+    /// Findings hidden by the baseline
+    #[serde(skip)]
+    pub baselined: Vec<Finding>,
+    #[serde(skip)]
+    pub graph: Option<CallGraph>,
+    pub metrics: Metrics,
+}
+impl Output {
+    /// Applies allow rules and the baseline to findings collected without them
+    pub fn apply(&mut self, allow_rules: &[AllowRule], baseline: Option<&Baseline>) {
+        for mut finding in std::mem::take(&mut self.findings) {
+            finding.justification = justification(allow_rules, &finding);
+            if baseline.is_some_and(|b| b.contains(&finding)) {
+                self.baselined.push(finding);
+            } else {
+                self.findings.push(finding);
+            }
+        }
+    }
+}
+fn justification(allow_rules: &[AllowRule], finding: &Finding) -> Option<String> {
+    allow_rules
+        .iter()
+        .find(|rule| rule.matches(finding))
+        .map(|rule| rule.justification.clone())
+}
 pub struct Builder<'a> {
     db: &'a RootDatabase,
+    vfs: &'a Vfs,
     krate: Crate,
     semantics: Semantics<'a, RootDatabase>,
     mode: Mode,
@@ -25,12 +301,31 @@ pub struct Builder<'a> {
     whitelist: Vec<CrateId>,
     bars: &'a MultiProgress,
     pub visited: FxHashSet<FunctionId>,
+    pub findings: Vec<Finding>,
+    /// Print findings as they are found, in human readable format
+    pub print_findings: bool,
+    pub allow_rules: &'a [AllowRule],
+    /// Findings in the baseline are counted, but not reported
+    pub baseline: Option<&'a Baseline>,
+    pub baselined: Vec<Finding>,
+    /// Report integer arithmetic that traps on overflow, as in the audited cargo profile
+    pub overflow_checks: bool,
+    /// Record the call graph when tracing functions
+    pub graph: Option<CallGraph>,
+    /// Current entry point in `Mode::EntryPoints`, and whether its header is printed
+    endpoint: Option<(String, bool)>,
+    /// Trace all impls of a trait method when the impl cannot be determined.
+    pub all_impls: bool,
+    /// Trait methods called on a type parameter, which are warned about once
+    type_param_calls: FxHashSet<hir::Function>,
+    pub metrics: Metrics,
 }
 impl<'a> Builder<'a> {
     pub fn new(
         bars: &'a MultiProgress,
         is_verbose: bool,
         db: &'a RootDatabase,
+        vfs: &'a Vfs,
         krate: Crate,
         whitelist: Vec<CrateId>,
         mode: Mode,
@@ -39,15 +334,40 @@ impl<'a> Builder<'a> {
             bars,
             is_verbose,
             db,
+            vfs,
             krate,
             mode,
             whitelist,
             semantics: Semantics::new(db),
             visited: FxHashSet::default(),
+            findings: Vec::new(),
+            print_findings: true,
+            allow_rules: &[],
+            baseline: None,
+            baselined: Vec::new(),
+            overflow_checks: false,
+            graph: None,
+            endpoint: None,
+            all_impls: false,
+            type_param_calls: FxHashSet::default(),
+            metrics: Metrics::default(),
+        }
+    }
+    /// Results that outlive the database borrowed by the builder
+    pub fn finish(self) -> Output {
+        Output {
+            visited: self.visited.len(),
+            findings: self.findings,
+            baselined: self.baselined,
+            graph: self.graph,
+            metrics: self.metrics,
         }
     }
     pub fn build(&mut self) {
         if self.whitelist.contains(&CrateId::from(self.krate)) {
+            let krate = crate_name(self.krate, self.db);
+            self.metrics
+                .add_whitelisted(krate, self.krate.version(self.db));
             return;
         }
         let name = crate_name(self.krate, self.db);
@@ -58,13 +378,31 @@ impl<'a> Builder<'a> {
         };
         let bar = create_bar(self.bars, format!("Auditing crate {name}..."));
         let module = self.krate.root_module();
-        self.process_module(module);
-        for impl_ in hir::Impl::all_in_crate(self.db, self.krate) {
-            self.process_impl(impl_);
+        if self.mode == Mode::EntryPoints {
+            self.process_entry_points(module);
+        } else {
+            self.process_module(module);
+            for impl_ in hir::Impl::all_in_crate(self.db, self.krate) {
+                self.process_impl(impl_);
+            }
         }
         bar.finish_and_clear();
         self.bars.remove(&bar);
     }
+    /// Trace each entry point with a fresh `visited` set, so that shared functions are reported for every entry point.
+    fn process_entry_points(&mut self, module: hir::Module) {
+        let mut entry_points = Vec::new();
+        collect_entry_points(self.db, module, &mut entry_points);
+        let mut visited = FxHashSet::default();
+        for (func, attr) in entry_points {
+            let name = func.name(self.db).display(self.db).to_string();
+            self.endpoint = Some((format!("{attr} `{name}`"), false));
+            self.process_function(func, &mut Vec::new());
+            visited.extend(self.visited.drain());
+        }
+        self.endpoint = None;
+        self.visited = visited;
+    }
     fn process_module(&mut self, module: hir::Module) {
         trace!("Processing module: {}", module.display(self.db));
         let decls = module.declarations(self.db);
@@ -76,10 +414,66 @@ impl<'a> Builder<'a> {
         trace!("Processing def: {:?}", def.name(self.db));
         match def {
             hir::ModuleDef::Module(module) => self.process_module(module),
+            // proc-macro crates run in the compiler, so their statics are not canister state
+            hir::ModuleDef::Static(s)
+                if self.mode == Mode::ScanExports && !is_proc_macro(self.krate, self.db) =>
+            {
+                self.process_static(s)
+            }
+            hir::ModuleDef::Const(c)
+                if self.mode == Mode::ScanExports && !is_proc_macro(self.krate, self.db) =>
+            {
+                self.process_const(c)
+            }
             hir::ModuleDef::Function(func) => self.process_function(func, &mut Vec::new()),
             _ => (),
         }
     }
+    fn process_static(&mut self, s: hir::Static) {
+        use ra_ap_hir::HasAttrs;
+        let attrs = s.attrs(self.db);
+        if let Some(section) = attrs.by_key("link_section").string_value() {
+            let detail = match system_api::decode_custom_section(section) {
+                Some(desc) => format!("{section} ({desc})"),
+                None => section.to_string(),
+            };
+            let location = self.def_location(s);
+            self.report(Kind::CustomSection, s, Some(detail), location, &[]);
+        }
+        let ty = s.ty(self.db);
+        let ty_name = ty.display(self.db).to_string();
+        let detail = if s.is_mut(self.db) {
+            Some(format!("static mut {ty_name}"))
+        } else if ty
+            .as_adt()
+            .is_some_and(|adt| adt.name(self.db) == s.name(self.db))
+        {
+            // lazy_static! defines a type with the same name as the static
+            Some("lazy_static".to_string())
+        } else {
+            global_state_type(&ty_name).then(|| format!("static {ty_name}"))
+        };
+        if let Some(detail) = detail {
+            let location = self.def_location(s);
+            self.report(Kind::GlobalState, s, Some(detail), location, &[]);
+        }
+    }
+    /// `thread_local!` expands to a `LocalKey` constant
+    fn process_const(&mut self, c: hir::Const) {
+        let ty = c.ty(self.db);
+        if !ty.display(self.db).to_string().starts_with("LocalKey<") {
+            return;
+        }
+        let Some(inner) = ty.type_arguments().next() else {
+            return;
+        };
+        let inner = inner.display(self.db).to_string();
+        if global_state_type(&inner) {
+            let location = self.def_location(c);
+            let detail = format!("thread_local {inner}");
+            self.report(Kind::GlobalState, c, Some(detail), location, &[]);
+        }
+    }
     fn process_impl(&mut self, impl_: hir::Impl) {
         impl_.items(self.db).into_iter().for_each(|item| {
             if let hir::AssocItem::Function(func) = item {
@@ -88,69 +482,110 @@ impl<'a> Builder<'a> {
         });
     }
     fn process_function(&mut self, func: hir::Function, path: &mut Vec<String>) {
-        use ra_ap_base_db::CrateOrigin;
         use ra_ap_hir::{HasAttrs, HasContainer, ItemContainer};
         use ra_ap_syntax::ast::AstNode;
-        if !self.visited.insert(func.into()) {
-            return;
-        }
         let krate = func.module(self.db).krate();
-        if matches!(
-            krate.origin(self.db),
-            CrateOrigin::Rustc { .. } | CrateOrigin::Lang(_)
-        ) {
+        let is_sysroot = is_sysroot(krate, self.db);
+        if let (Some(graph), Some(caller)) = (self.graph.as_mut(), path.last()) {
+            // record the edge even if the callee is already visited
+            if !is_sysroot {
+                graph.add_edge(caller, display_path(func.into(), self.db));
+            }
+        }
+        // sysroot functions are not traced, so they are not counted as visited
+        if is_sysroot || !self.visited.insert(func.into()) {
             return;
         }
-        let is_whitelisted = self.whitelist.contains(&CrateId::from(krate));
         let name = display_path(func.into(), self.db);
+        let is_whitelisted = self.whitelist.contains(&CrateId::from(krate));
+        if let Some(graph) = self.graph.as_mut() {
+            graph.add_node(
+                name.clone(),
+                crate_name(krate, self.db),
+                krate.version(self.db),
+            );
+        }
+        self.metrics.add_function(
+            name.clone(),
+            crate_name(krate, self.db),
+            krate.version(self.db),
+            is_whitelisted,
+        );
         let msg = format!("Processing function: {name}...");
         info!("{msg}");
         let bar = create_bar(self.bars, msg);
         path.push(name.clone());
         if let ItemContainer::ExternBlock() = func.container(self.db) {
-            self.report(
-                is_whitelisted,
-                path,
-                format!(
-                    "{} {} is an {} import!",
-                    style("[Import]").red().bold(),
-                    style(&name).red(),
-                    style("external").red()
-                ),
-            );
+            self.process_import(func, path);
         }
         match self.mode {
-            Mode::TraceFunctions => {
+            Mode::TraceFunctions | Mode::EntryPoints => {
                 let Some(ast) = self.semantics.source(func) else {
-                    self.report(is_whitelisted, &[], format!("cannot get source for {name}"));
+                    if !is_whitelisted {
+                        self.print(format!("cannot get source for {name}"));
+                    }
                     return;
                 };
-                self.process_syntax_node(&name, is_whitelisted, path, ast.value.syntax());
+                if krate == self.krate
+                    && func.is_async(self.db)
+                    && crate::candid::get_entry_point_attr(&func, self.db).as_deref()
+                        == Some("update")
+                {
+                    self.process_borrows(func, path, &ast.value);
+                }
+                self.process_syntax_node(func, path, ast.value.syntax());
             }
             Mode::ScanExports => {
-                let attrs = func.attrs(self.db);
-                if let Some(export) = attrs.export_name() {
-                    self.report(
-                        is_whitelisted,
-                        path,
-                        format!(
-                            "{} {} exports {}",
-                            style("[Export]").yellow().bold(),
-                            style(&name).yellow(),
-                            style(export).yellow()
-                        ),
+                // unsafe blocks are only reported when tracing, but counted for the metrics
+                let has_unsafe = self
+                    .semantics
+                    .source(func)
+                    .is_some_and(|ast| self.has_unsafe(ast.value.syntax()));
+                if has_unsafe {
+                    self.metrics.add_unsafe(
+                        name.clone(),
+                        crate_name(krate, self.db),
+                        krate.version(self.db),
                     );
                 }
+                let attrs = func.attrs(self.db);
+                let export = if let Some(export) = attrs.export_name() {
+                    Some(export.to_string())
+                } else if attrs.by_key("no_mangle").exists() {
+                    Some(func.name(self.db).display(self.db).to_string())
+                } else {
+                    None
+                };
+                if let Some(export) = export {
+                    let detail = match system_api::decode_export(&export) {
+                        Some(method) => format!("{export} ({method})"),
+                        None => export,
+                    };
+                    let location = self.def_location(func);
+                    self.report(Kind::Export, func, Some(detail), location, path);
+                }
             }
         }
         bar.finish_and_clear();
         self.bars.remove(&bar);
         path.pop();
     }
+    /// Unsafe blocks, including the ones from macro expansions
+    fn has_unsafe(&self, ast: &SyntaxNode) -> bool {
+        use ra_ap_syntax::{ast, match_ast, AstNode};
+        ast.descendants().any(|node| {
+            match_ast! {
+                match node {
+                    ast::MacroCall(m) => self.semantics.expand(&m).is_some_and(|m| self.has_unsafe(&m)),
+                    ast::BlockExpr(b) => b.unsafe_token().is_some(),
+                    _ => false,
+                }
+            }
+        })
+    }
     fn process_syntax_node(
         &mut self,
-        name: &str,
-        is_whitelisted: bool,
+        func: hir::Function,
         path: &mut Vec<String>,
         ast: &SyntaxNode,
     ) {
@@ -159,11 +594,15 @@ impl<'a> Builder<'a> {
         for node in ast.descendants() {
             match_ast! {
                 match node {
-                    ast::MacroCall(m) => if let Some(m) = self.semantics.expand(&m) {
-                        self.process_syntax_node(name, is_whitelisted, path, &m);
+                    // the expansion of a panicking macro is not reported again
+                    ast::MacroCall(m) => if !self.process_panic_macro(func, path, &m) {
+                        if let Some(m) = self.semantics.expand(&m) {
+                            self.process_syntax_node(func, path, &m);
+                        }
                     },
                     ast::BlockExpr(b) =>if b.unsafe_token().is_some() {
-                        self.report(is_whitelisted, path, format!("{} {} contains {} blocks!", style("[Unsafe]").yellow().bold(), style(name).yellow(), style("unsafe").yellow()));
+                        let location = self.location(b.syntax());
+                        self.report(Kind::Unsafe, func, None, location, path);
                     },
                     ast::AwaitExpr(e) => if let Some(f) = self.semantics.resolve_await_to_poll(&e) {
                         self.process_function(f, path);
@@ -171,63 +610,54 @@ impl<'a> Builder<'a> {
                     ast::PrefixExpr(e) => if let Some(f) = self.semantics.resolve_prefix_expr(&e) {
                         self.process_function(f, path);
                     },
-                    ast::IndexExpr(e) => if let Some(f) = self.semantics.resolve_index_expr(&e) {
-                        self.process_function(f, path);
+                    ast::IndexExpr(e) => match self.semantics.resolve_index_expr(&e) {
+                        Some(f) if !is_sysroot(f.module(self.db).krate(), self.db) => self.process_function(f, path),
+                        // indexing into std collections and builtin slices panics when out of bounds
+                        // the base type is unknown when inference fails, so it may not panic at all
+                        _ => if let Some(ty) = e.base().and_then(|b| self.semantics.type_of_expr(&b)).filter(|ty| !ty.original.contains_unknown()) {
+                            let ty = ty.original.display(self.db).to_string();
+                            // `table[i & 0xff]` on a `[T; 256]` is always in bounds
+                            let in_bounds = array_len(&ty).zip(e.index().and_then(|i| index_bound(&i))).is_some_and(|(len, bound)| bound < len);
+                            if in_bounds {
+                                continue;
+                            }
+                            let detail = format!("`[]` on `{ty}`");
+                            let location = self.location(e.syntax());
+                            self.report_panic(func, detail, location, path);
+                        }
                     },
-                    ast::BinExpr(e) => if let Some(f) = self.semantics.resolve_bin_expr(&e) {
-                        self.process_function(f, path);
+                    ast::BinExpr(e) => match self.semantics.resolve_bin_expr(&e) {
+                        Some(f) if !is_sysroot(f.module(self.db).krate(), self.db) => self.process_function(f, path),
+                        _ => self.process_overflow(func, path, &e),
                     },
                     ast::TryExpr(e) => if let Some(f) = self.semantics.resolve_try_expr(&e) {
                         self.process_function(f, path);
                     },
                     ast::MethodCallExpr(m) => if let Some(f) = self.semantics.resolve_method_call(&m) {
-                        self.process_function(f, path);
-                        /*
-                            // Looking at m's type isn't correct. Need to inspect type parameter in the signature.
-                            if let ItemContainer::Trait(t) = f.container(self.db) {
-                                if let Some(ty) = self.semantics.type_of_expr(&m.clone().into()) {
-                                    let ty = ty.adjusted().display(self.db).to_string();
-                                    let impls = hir::Impl::all_for_trait(self.db, t);
-                                    if let Some(impl_) = impls.iter().find(|i| i.self_ty(self.db).display(self.db).to_string() == ty) {
-                                        let func = impl_.items(self.db).into_iter().find_map(|assoc| {
-                                            if let AssocItem::Function(func) = assoc {
-                                                if func.name(self.db) == f.name(self.db) {
-                                                    return Some(func);
-                                                }
-                                            };
-                                            None
-                                        });
-                                        if let Some(f) = func {
-                                            warn!("{m} : {ty}");
-                                            self.process_function(f, path);
-                                        }
-                                    }
-                                }
-                            }
-                         */
+                        self.process_panic_call(func, path, f, m.syntax());
+                        self.process_unsupported_call(func, path, f, m.syntax());
+                        let self_ty = m.receiver().and_then(|r| self.semantics.type_of_expr(&r)).map(|ty| ty.adjusted());
+                        self.process_call(f, self_ty, path);
                     },
                     ast::PathExpr(path_expr) => if let Some(p) = path_expr.path() {
                         if let Some(PathResolution::Def(hir::ModuleDef::Function(f))) = self.semantics.resolve_path(&p) {
-                            self.process_function(f, path);
-                            /*
-                            // This is an over-approximation. Ideally, we can find the right impl.
-                            if let ItemContainer::Trait(t) = f.container(self.db) {
-                                let impls = hir::Impl::all_for_trait(self.db, t);
-                                let impls: Vec<_> = impls.into_iter().flat_map(|i| i.items(self.db).into_iter().filter_map(|assoc| match assoc {
-                                    AssocItem::Function(func) if func.name(self.db) == f.name(self.db) => Some(func),
-                                    _ => None,
-                                })).collect();
-                                //warn!("{} {} => {:?}", node, impls.len(), t);
-                                for f in impls {
-                                    self.process_function(f, path);
-                                }
-                            }
-                            */
+                            self.process_panic_call(func, path, f, path_expr.syntax());
+                            self.process_unsupported_call(func, path, f, path_expr.syntax());
+                            self.process_canister_call(func, path, f, &path_expr);
+                            let self_ty = p.qualifier().and_then(|q| self.resolve_qualifier_type(&q));
+                            self.process_call(f, self_ty, path);
                         }
                     },
                     ast::Expr(e) => if let Some(call) = self.semantics.resolve_expr_as_callable(&e) {
-                        if let CallableKind::Function(f) = call.kind() {
-                            self.process_function(f, path);
+                        match call.kind() {
+                            CallableKind::Function(f) => self.process_function(f, path),
+                            // Closure bodies are traced in their defining function, and function
+                            // items are traced where they are used as values, e.g., coerced into a
+                            // `Box<dyn Fn>` or a `fn` pointer. Calling the value adds no new code.
+                            CallableKind::Closure(_) | CallableKind::FnPtr | CallableKind::FnImpl(_) => {
+                                trace!("Calling a function value: {e}");
+                            }
+                            CallableKind::TupleStruct(_) | CallableKind::TupleEnumVariant(_) => (),
                         }
                     },
                     _ => (),
@@ -235,27 +665,790 @@ impl<'a> Builder<'a> {
             }
         }
     }
-    fn report(
-        &self,
-        is_whitelisted: bool,
+    /// `unwrap` and `expect` on `Option` and `Result`
+    fn process_panic_call(
+        &mut self,
+        func: hir::Function,
+        path: &[String],
+        callee: hir::Function,
+        node: &SyntaxNode,
+    ) {
+        let name = display_path(callee.into(), self.db);
+        if PANIC_METHODS.contains(&name.as_str()) {
+            let short: Vec<_> = name.rsplit("::").take(2).collect();
+            let detail = format!("`{}::{}`", short[1], short[0]);
+            let location = self.location(node);
+            self.report_panic(func, detail, location, path);
+        }
+    }
+    /// Non-deterministic and unsupported APIs, reported at the call site that enters them
+    fn process_unsupported_call(
+        &mut self,
+        func: hir::Function,
+        path: &[String],
+        callee: hir::Function,
+        node: &SyntaxNode,
+    ) {
+        let krate = callee.module(self.db).krate();
+        // calls within the API crate itself are not call sites of the canister
+        if krate == func.module(self.db).krate() {
+            return;
+        }
+        let name = display_path(callee.into(), self.db);
+        let Some((api, reason)) = unsupported_api(&name) else {
+            return;
+        };
+        if api == "getrandom::" && has_custom_getrandom(krate, self.db) {
+            return;
+        }
+        let detail = format!("`{name}` ({reason})");
+        let location = self.location(node);
+        self.report(Kind::UnsupportedApi, func, Some(detail), location, path);
+    }
+    /// Inter-canister calls and cycles transfers, with the callee and method when they are constant
+    fn process_canister_call(
+        &mut self,
+        func: hir::Function,
+        path: &[String],
+        callee: hir::Function,
+        path_expr: &ra_ap_syntax::ast::PathExpr,
+    ) {
+        use ra_ap_syntax::ast::{self, HasArgList};
+        use ra_ap_syntax::AstNode;
+        let name = display_path(callee.into(), self.db);
+        let short = name.rsplit("::").next().unwrap_or_default();
+        let detail = if let Some(api) = name.strip_prefix("ic_cdk::api::management_canister::") {
+            format!("management canister call `{api}`")
+        } else if let Some((_, desc)) = CYCLES_APIS.iter().find(|(api, _)| *api == name) {
+            desc.to_string()
+        } else if CALL_APIS.contains(&name.as_str()) {
+            let args: Vec<_> = path_expr
+                .syntax()
+                .parent()
+                .and_then(ast::CallExpr::cast)
+                .and_then(|call| call.arg_list())
+                .map(|list| list.args().collect())
+                .unwrap_or_default();
+            let [callee, method] = [0, 1].map(|i| {
+                args.get(i)
+                    .and_then(|arg| self.constant_value(arg))
+                    .unwrap_or_else(|| "<dynamic>".to_string())
+            });
+            format!("`{short}` to {callee}, method {method}")
+        } else {
+            return;
+        };
+        let location = self.location(path_expr.syntax());
+        self.report(Kind::CanisterCall, func, Some(detail), location, path);
+    }
+    /// Value of an expression that resolves to a literal or a constant, following local bindings
+    /// and the `Principal` constructors
+    fn constant_value(&self, expr: &ra_ap_syntax::ast::Expr) -> Option<String> {
+        use ra_ap_hir::PathResolution;
+        use ra_ap_syntax::ast::{self, HasArgList, HasName};
+        use ra_ap_syntax::AstNode;
+        match expr {
+            ast::Expr::Literal(lit) => Some(literal_value(lit)),
+            ast::Expr::RefExpr(e) => self.constant_value(&e.expr()?),
+            ast::Expr::ParenExpr(e) => self.constant_value(&e.expr()?),
+            // `Principal::from_text("...").unwrap()`
+            ast::Expr::MethodCallExpr(e)
+                if e.name_ref()
+                    .is_some_and(|name| matches!(name.text().as_str(), "unwrap" | "expect")) =>
+            {
+                self.constant_value(&e.receiver()?)
+            }
+            ast::Expr::CallExpr(e) => {
+                let ast::Expr::PathExpr(callee) = e.expr()? else {
+                    return None;
+                };
+                let PathResolution::Def(hir::ModuleDef::Function(f)) =
+                    self.semantics.resolve_path(&callee.path()?)?
+                else {
+                    return None;
+                };
+                let name = display_path(f.into(), self.db);
+                if name.ends_with("Principal::management_canister") {
+                    Some("`aaaaa-aa`".to_string())
+                } else if name.ends_with("Principal::from_text") {
+                    self.constant_value(&e.arg_list()?.args().next()?)
+                } else {
+                    None
+                }
+            }
+            ast::Expr::PathExpr(e) => match self.semantics.resolve_path(&e.path()?)? {
+                // The value lives in another syntax tree, which is not known to `Semantics`
+                PathResolution::Def(hir::ModuleDef::Const(c)) => match c.value(self.db)? {
+                    ast::Expr::Literal(lit) => Some(literal_value(&lit)),
+                    value => Some(value.to_string()),
+                },
+                PathResolution::Local(local) => {
+                    let name = local.name(self.db).display(self.db).to_string();
+                    let start = expr.syntax().text_range().start();
+                    let stmt = expr
+                        .syntax()
+                        .ancestors()
+                        .filter_map(ast::StmtList::cast)
+                        .find_map(|list| {
+                            list.statements()
+                                .filter_map(|stmt| match stmt {
+                                    ast::Stmt::LetStmt(stmt) => Some(stmt),
+                                    _ => None,
+                                })
+                                .filter(|stmt| {
+                                    stmt.syntax().text_range().end() <= start
+                                        && matches!(stmt.pat(), Some(ast::Pat::IdentPat(p)) if p.name().is_some_and(|n| n.text() == name))
+                                })
+                                .last()
+                        })?;
+                    self.constant_value(&stmt.initializer()?)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+    /// `panic!`, `unreachable!`, `todo!` and `unimplemented!` from std. Returns true if reported.
+    fn process_panic_macro(
+        &mut self,
+        func: hir::Function,
+        path: &[String],
+        m: &ra_ap_syntax::ast::MacroCall,
+    ) -> bool {
+        use ra_ap_syntax::AstNode;
+        let Some(mac) = self.semantics.resolve_macro_call(m) else {
+            return false;
+        };
+        let name = mac.name(self.db).display(self.db).to_string();
+        if is_sysroot(mac.module(self.db).krate(), self.db) && PANIC_MACROS.contains(&name.as_str())
+        {
+            let location = self.location(m.syntax());
+            self.report_panic(func, format!("`{name}!`"), location, path);
+            return true;
+        }
+        false
+    }
+    /// Arithmetic on integers, which traps on overflow when overflow checks are enabled, and on
+    /// division by zero regardless of the profile
+    fn process_overflow(
+        &mut self,
+        func: hir::Function,
+        path: &[String],
+        e: &ra_ap_syntax::ast::BinExpr,
+    ) {
+        use ra_ap_syntax::ast::{self, ArithOp, BinaryOp};
+        use ra_ap_syntax::AstNode;
+        if self.endpoint.is_none() {
+            return;
+        }
+        let op = match e.op_kind() {
+            Some(BinaryOp::ArithOp(op)) | Some(BinaryOp::Assignment { op: Some(op) }) => op,
+            _ => return,
+        };
+        let is_division = matches!(op, ArithOp::Div | ArithOp::Rem);
+        if matches!(op, ArithOp::BitOr | ArithOp::BitXor | ArithOp::BitAnd)
+            || (!is_division && !self.overflow_checks)
+        {
+            return;
+        }
+        let (Some(lhs), Some(rhs)) = (e.lhs(), e.rhs()) else {
+            return;
+        };
+        // constant expressions are checked at compile time
+        if matches!((&lhs, &rhs), (ast::Expr::Literal(_), ast::Expr::Literal(_))) {
+            return;
+        }
+        // `MIN / -1` needs a negative divisor, which is not a literal
+        let literal = match &rhs {
+            ast::Expr::Literal(lit) => int_literal(lit),
+            _ => None,
+        };
+        if is_division && literal.is_some_and(|n| n != 0) {
+            return;
+        }
+        // compound assignments borrow the lhs mutably
+        let Some(ty) = self
+            .semantics
+            .type_of_expr(&lhs)
+            .map(|ty| ty.adjusted().strip_references())
+        else {
+            return;
+        };
+        if !ty.is_int_or_uint() {
+            return;
+        }
+        let width = int_width(&ty.display(self.db).to_string());
+        if matches!(op, ArithOp::Shl | ArithOp::Shr)
+            && literal.zip(width).is_some_and(|(n, width)| n < width)
+        {
+            return;
+        }
+        let op = e
+            .op_token()
+            .map(|t| t.text().to_string())
+            .unwrap_or_default();
+        let detail = format!("`{op}` on `{}`", ty.display(self.db));
+        let location = self.location(e.syntax());
+        self.report_panic(func, detail, location, path);
+    }
+    /// Report locals holding a `RefCell` borrow or lock guard whose scope spans an `.await`
+    fn process_borrows(
+        &mut self,
+        func: hir::Function,
         path: &[String],
-        msg: impl std::convert::AsRef<str> + std::fmt::Display,
+        ast: &ra_ap_syntax::ast::Fn,
     ) {
-        if !is_whitelisted {
-            if self.is_verbose {
-                error!("{msg}");
+        use ra_ap_syntax::ast::{self, HasName};
+        use ra_ap_syntax::AstNode;
+        let Some(body) = ast.body() else {
+            return;
+        };
+        let bindings = body
+            .syntax()
+            .descendants()
+            .filter_map(ast::LetStmt::cast)
+            // closures and async blocks are separate bodies, awaited elsewhere
+            .filter(|stmt| !in_nested_body(stmt.syntax(), body.syntax()))
+            .flat_map(|stmt| {
+                let pats = stmt.pat().into_iter().flat_map(|pat| {
+                    pat.syntax()
+                        .descendants()
+                        .filter_map(ast::IdentPat::cast)
+                        .collect::<Vec<_>>()
+                });
+                pats.map(move |pat| (stmt.clone(), pat))
+            });
+        for (stmt, pat) in bindings {
+            let (Some(name), Some(scope)) = (pat.name(), stmt.syntax().parent()) else {
+                continue;
+            };
+            let Some(ty) = self.semantics.type_of_binding_in_pat(&pat) else {
+                continue;
+            };
+            let ty = ty.display(self.db).to_string();
+            if !GUARD_TYPES.iter().any(|t| ty.starts_with(t)) {
+                continue;
+            }
+            let name = name.text().to_string();
+            let start = stmt.syntax().text_range().end();
+            // The borrow lives until the end of the block, or until a `drop` statement in the same
+            // block. A `drop` in a nested branch may not run, so it doesn't end the borrow.
+            let end = scope
+                .children()
+                .filter_map(ast::ExprStmt::cast)
+                .filter_map(|stmt| match stmt.expr()? {
+                    ast::Expr::CallExpr(call) => Some(call),
+                    _ => None,
+                })
+                .filter(|call| call.syntax().text_range().start() >= start && is_drop(call, &name))
+                .map(|call| call.syntax().text_range().start())
+                .min()
+                .unwrap_or(scope.text_range().end());
+            let Some(await_expr) = scope
+                .descendants()
+                .filter_map(ast::AwaitExpr::cast)
+                .filter(|e| !in_nested_body(e.syntax(), &scope))
+                .find(|e| {
+                    let range = e.syntax().text_range();
+                    range.start() >= start && range.end() <= end
+                })
+            else {
+                continue;
+            };
+            let detail = match self.location(await_expr.syntax()) {
+                Some(loc) => format!("`{name}: {ty}` across .await at line {}", loc.line),
+                None => format!("`{name}: {ty}` across .await"),
+            };
+            let location = self.location(pat.syntax());
+            self.report(Kind::BorrowAcrossAwait, func, Some(detail), location, path);
+        }
+    }
+    fn process_import(&mut self, func: hir::Function, path: &[String]) {
+        use ra_ap_hir::HasAttrs;
+        let location = self.def_location(func);
+        let module = self.import_module(func);
+        let attrs = func.attrs(self.db);
+        let name = match attrs.by_key("link_name").string_value() {
+            Some(name) => name.to_string(),
+            None => func.name(self.db).display(self.db).to_string(),
+        };
+        let params: Vec<_> = func
+            .params_without_self(self.db)
+            .iter()
+            .map(|p| p.ty().display(self.db).to_string())
+            .collect();
+        let ret = func.ret_type(self.db);
+        let ret = if ret.is_unit() || ret.is_never() {
+            None
+        } else {
+            Some(ret.display(self.db).to_string())
+        };
+        match system_api::check_import(&module, &name, &params, ret.as_deref()) {
+            Ok(api) => {
+                let detail = format!("{module}.{name} (contexts: {})", api.contexts);
+                self.report(Kind::Import, func, Some(detail), location, path);
+            }
+            Err(reason) => {
+                let detail = format!("{module}.{name}");
+                self.report(Kind::Import, func, Some(detail), location.clone(), path);
+                self.report(Kind::InvalidImport, func, Some(reason), location, path);
+            }
+        }
+    }
+    /// Read `#[link(wasm_import_module = "...")]` from the extern block. Wasm defaults to the `env` module.
+    fn import_module(&self, func: hir::Function) -> String {
+        use ra_ap_syntax::ast::{self, AstNode, HasAttrs};
+        let module = self.semantics.source(func).and_then(|ast| {
+            let block = ast
+                .value
+                .syntax()
+                .ancestors()
+                .find_map(ast::ExternBlock::cast)?;
+            block.attrs().find_map(|attr| {
+                let (name, tt) = attr.as_simple_call()?;
+                if name != "link" {
+                    return None;
+                }
+                let tt = tt.to_string();
+                let tt = tt.trim_start_matches('(').trim_end_matches(')');
+                tt.split(',').find_map(|kv| {
+                    let (key, value) = kv.split_once('=')?;
+                    (key.trim() == "wasm_import_module")
+                        .then(|| value.trim().trim_matches('"').to_string())
+                })
+            })
+        });
+        module.unwrap_or_else(|| "env".to_string())
+    }
+    /// Process a resolved call. rust-analyzer resolves trait methods to the impl method only when the
+    /// substitution is concrete; otherwise we get the trait declaration, and try to pick the impl from `self_ty`.
+    fn process_call(
+        &mut self,
+        f: hir::Function,
+        self_ty: Option<hir::Type>,
+        path: &mut Vec<String>,
+    ) {
+        use ra_ap_hir::{HasContainer, ItemContainer};
+        let ItemContainer::Trait(t) = f.container(self.db) else {
+            self.process_function(f, path);
+            return;
+        };
+        let name = f.name(self.db);
+        let find_method = |impl_: &hir::Impl| {
+            impl_
+                .items(self.db)
+                .into_iter()
+                .find_map(|assoc| match assoc {
+                    hir::AssocItem::Function(func) if func.name(self.db) == name => Some(func),
+                    _ => None,
+                })
+        };
+        let self_ty = self_ty.map(|ty| ty.strip_references());
+        let type_param = self_ty.as_ref().and_then(|ty| ty.as_type_param(self.db));
+        let self_ty = self_ty.filter(|ty| !ty.contains_unknown() && type_param.is_none());
+        // `dyn Trait` receivers can be any impl of the trait
+        let is_dyn = self_ty
+            .as_ref()
+            .is_some_and(|ty| ty.as_dyn_trait().is_some());
+        if let Some(ty) = self_ty.filter(|_| !is_dyn) {
+            let impl_ = hir::Impl::all_for_type(self.db, ty)
+                .into_iter()
+                .find(|i| i.trait_(self.db) == Some(t));
+            if let Some(impl_) = impl_ {
+                // If the impl doesn't override the method, the default body in the trait is called.
+                let f = find_method(&impl_).unwrap_or(f);
+                self.process_function(f, path);
+                return;
+            }
+        }
+        if let Some(param) = type_param.filter(|_| !self.all_impls) {
+            // Functions are traced once for all their instantiations, so the impl is unknown
+            if self.type_param_calls.insert(f) {
+                let caller = path.last().cloned().unwrap_or_default();
+                self.warn(format!(
+                    "{caller} calls {} on type parameter `{}`, only the default body is traced. Use --all-impls to trace every impl.",
+                    display_path(f.into(), self.db),
+                    param.name(self.db).display(self.db)
+                ));
+            }
+        }
+        // The default body (if any) is always a candidate.
+        self.process_function(f, path);
+        if self.all_impls || is_dyn {
+            let impls: Vec<_> = hir::Impl::all_for_trait(self.db, t)
+                .iter()
+                .filter_map(find_method)
+                .collect();
+            info!(
+                "Over-approximating {} to {} impls",
+                display_path(f.into(), self.db),
+                impls.len()
+            );
+            if is_dyn {
+                // A virtual node labels the over-approximated calls in paths and the call graph
+                let krate = t.module(self.db).krate();
+                let node = format!(
+                    "<dyn {}>::{}",
+                    display_path(t.into(), self.db),
+                    name.display(self.db)
+                );
+                if let Some(graph) = self.graph.as_mut() {
+                    if let Some(caller) = path.last() {
+                        graph.add_edge(caller, node.clone());
+                    }
+                    graph.add_node(
+                        node.clone(),
+                        crate_name(krate, self.db),
+                        krate.version(self.db),
+                    );
+                }
+                path.push(node);
+                for f in impls {
+                    self.process_function(f, path);
+                }
+                path.pop();
             } else {
-                self.bars.println(msg).unwrap();
+                for f in impls {
+                    self.process_function(f, path);
+                }
+            }
+        }
+    }
+    fn resolve_qualifier_type(&self, qualifier: &ra_ap_syntax::ast::Path) -> Option<hir::Type> {
+        use ra_ap_hir::PathResolution;
+        // <Ty as Trait>::method
+        if let Some(ty) = qualifier.segment().and_then(|s| s.ty()) {
+            return self.semantics.resolve_type(&ty);
+        }
+        match self.semantics.resolve_path(qualifier)? {
+            PathResolution::Def(hir::ModuleDef::Adt(adt)) => Some(adt.ty(self.db)),
+            PathResolution::SelfType(impl_) => Some(impl_.self_ty(self.db)),
+            PathResolution::TypeParam(param) => Some(param.ty(self.db)),
+            _ => None,
+        }
+    }
+    fn def_location<D>(&self, def: D) -> Option<Location>
+    where
+        D: hir::HasSource,
+        D::Ast: ra_ap_syntax::AstNode,
+    {
+        use ra_ap_syntax::ast::AstNode;
+        let ast = self.semantics.source(def)?;
+        self.location(ast.value.syntax())
+    }
+    fn location(&self, node: &SyntaxNode) -> Option<Location> {
+        use ra_ap_base_db::FileLoader;
+        use ra_ap_ide::LineIndex;
+        // Maps nodes from macro expansion to the macro call site
+        let range = self.semantics.original_range(node);
+        let index = LineIndex::new(&self.db.file_text(range.file_id));
+        let start = index.line_col(range.range.start());
+        let end = index.line_col(range.range.end());
+        // cached results are shared by every working directory, so the path is kept absolute
+        let file = self.vfs.file_path(range.file_id).to_string();
+        Some(Location {
+            file,
+            line: start.line + 1,
+            column: start.col + 1,
+            end_line: end.line + 1,
+            end_column: end.col + 1,
+        })
+    }
+    /// A trap only matters on a path from a canister method, so it is not reported elsewhere
+    fn report_panic(
+        &mut self,
+        func: hir::Function,
+        detail: String,
+        location: Option<Location>,
+        path: &[String],
+    ) {
+        if self.endpoint.is_some() {
+            self.report(Kind::Panic, func, Some(detail), location, path);
+        }
+    }
+    fn report(
+        &mut self,
+        kind: Kind,
+        def: impl Into<hir::ModuleDef>,
+        detail: Option<String>,
+        location: Option<Location>,
+        path: &[String],
+    ) {
+        let def = def.into();
+        let krate = def.module(self.db).unwrap().krate();
+        let mut finding = Finding {
+            kind,
+            function: display_path(def, self.db),
+            krate: crate_name(krate, self.db),
+            version: krate.version(self.db),
+            whitelisted: self.whitelist.contains(&CrateId::from(krate)),
+            endpoint: self.endpoint.as_ref().map(|(e, _)| e.clone()),
+            justification: None,
+            detail,
+            path: path.to_vec(),
+            location,
+            target: None,
+        };
+        finding.justification = justification(self.allow_rules, &finding);
+        self.metrics.add_finding(&finding);
+        if self.baseline.is_some_and(|b| b.contains(&finding)) {
+            self.baselined.push(finding);
+            return;
+        }
+        if self.print_findings && !finding.is_suppressed() {
+            if let Some((endpoint, printed)) = &mut self.endpoint {
+                if !*printed {
+                    *printed = true;
+                    let header = format!("{:>12} {endpoint}", style("Endpoint").cyan().bold());
+                    self.print(header);
+                }
             }
+            self.print(finding.message());
             if path.len() > 1 {
                 let path: Vec<String> = path.iter().map(|p| style(p).cyan().to_string()).collect();
                 let path = format!("  {} {}", style("└────> [Path]").green(), path.join(" -> "));
-                if self.is_verbose {
-                    error!("{path}");
-                } else {
-                    self.bars.println(path).unwrap();
+                self.print(path);
+            }
+        }
+        self.findings.push(finding);
+    }
+    fn print(&self, msg: impl std::convert::AsRef<str> + std::fmt::Display) {
+        if self.is_verbose {
+            error!("{msg}");
+        } else {
+            self.bars.println(msg).unwrap();
+        }
+    }
+    /// Printed even when the progress bars are hidden, e.g., in CI
+    fn warn(&self, msg: String) {
+        if self.is_verbose || self.bars.is_hidden() {
+            warn!("{msg}");
+        } else {
+            let msg = format!("{} {msg}", style("[Warning]").yellow().bold());
+            self.bars.println(msg).unwrap();
+        }
+    }
+}
+fn collect_entry_points(
+    db: &RootDatabase,
+    module: hir::Module,
+    res: &mut Vec<(hir::Function, String)>,
+) {
+    for def in module.declarations(db) {
+        match def {
+            hir::ModuleDef::Module(m) => collect_entry_points(db, m, res),
+            hir::ModuleDef::Function(func) => {
+                if let Some(attr) = crate::candid::get_entry_point_attr(&func, db) {
+                    res.push((func, attr));
                 }
             }
+            _ => (),
         }
     }
 }
+/// Path prefixes of APIs that are non-deterministic or trap on wasm32-unknown-unknown
+const UNSUPPORTED_APIS: [(&str, &str); 6] = [
+    (
+        "std::time::SystemTime::now",
+        "non-deterministic, use ic_cdk::api::time",
+    ),
+    (
+        "std::time::Instant::now",
+        "non-deterministic, use ic_cdk::api::time",
+    ),
+    ("std::fs::", "no filesystem"),
+    ("std::net::", "no network access"),
+    ("std::process::", "no processes"),
+    (
+        "getrandom::",
+        "no entropy source, use raw_rand from the management canister",
+    ),
+];
+/// Functions in `std::thread` that spawn threads or block. They are defined in private submodules
+/// that move between releases, e.g., `std::thread::scoped::scope`, so they are matched by name.
+const THREAD_APIS: [(&str, &str); 7] = [
+    ("spawn", "threads are not supported"),
+    ("scope", "threads are not supported"),
+    ("park", "blocking is not supported, use timers"),
+    ("park_timeout", "blocking is not supported, use timers"),
+    ("sleep", "blocking is not supported, use timers"),
+    ("sleep_ms", "blocking is not supported, use timers"),
+    ("sleep_until", "blocking is not supported, use timers"),
+];
+/// The matched API prefix and the reason it is unsupported
+fn unsupported_api(name: &str) -> Option<(&'static str, &'static str)> {
+    if let Some(rest) = name.strip_prefix("std::thread::") {
+        if rest.contains("Builder::") {
+            return Some(("std::thread::", "threads are not supported"));
+        }
+        let short = rest.rsplit("::").next().unwrap_or(rest);
+        return THREAD_APIS
+            .iter()
+            .find(|(api, _)| *api == short)
+            .map(|(_, reason)| ("std::thread::", *reason));
+    }
+    UNSUPPORTED_APIS
+        .iter()
+        .find(|(api, _)| name.starts_with(api))
+        .copied()
+}
+/// Functions making an outbound call, with the callee and method name as the first two arguments.
+/// The `Call` builder of ic-cdk 0.18 and later is not covered yet.
+const CALL_APIS: [&str; 9] = [
+    "ic_cdk::api::call::call",
+    "ic_cdk::api::call::call_with_payment",
+    "ic_cdk::api::call::call_with_payment128",
+    "ic_cdk::api::call::call_with_config",
+    "ic_cdk::api::call::call_raw",
+    "ic_cdk::api::call::call_raw128",
+    "ic_cdk::api::call::notify",
+    "ic_cdk::api::call::notify_with_payment128",
+    "ic_cdk::api::call::notify_raw",
+];
+const CYCLES_APIS: [(&str, &str); 4] = [
+    ("ic0::ic0::call_new", "raw call via `ic0::call_new`"),
+    (
+        "ic0::ic0::call_cycles_add",
+        "cycles transfer via `ic0::call_cycles_add`",
+    ),
+    (
+        "ic0::ic0::call_cycles_add128",
+        "cycles transfer via `ic0::call_cycles_add128`",
+    ),
+    ("ic0::ic0::call_perform", "raw call via `ic0::call_perform`"),
+];
+/// String literals are unquoted
+fn literal_value(lit: &ra_ap_syntax::ast::Literal) -> String {
+    use ra_ap_syntax::ast::LiteralKind;
+    match lit.kind() {
+        LiteralKind::String(s) => match s.value() {
+            Ok(value) => format!("`{value}`"),
+            Err(_) => lit.to_string(),
+        },
+        _ => lit.to_string(),
+    }
+}
+const PANIC_METHODS: [&str; 6] = [
+    "core::option::Option::unwrap",
+    "core::option::Option::expect",
+    "core::result::Result::unwrap",
+    "core::result::Result::expect",
+    "core::result::Result::unwrap_err",
+    "core::result::Result::expect_err",
+];
+const PANIC_MACROS: [&str; 4] = ["panic", "unreachable", "todo", "unimplemented"];
+/// `register_custom_getrandom!` requires the `custom` feature of getrandom 0.2, and the
+/// `getrandom_backend = "custom"` cfg selects the custom backend of getrandom 0.3
+fn has_custom_getrandom(krate: Crate, db: &RootDatabase) -> bool {
+    let cfg = krate.cfg(db);
+    cfg.get_cfg_values("feature").any(|f| f == "custom")
+        || cfg
+            .get_cfg_values("getrandom_backend")
+            .any(|b| b == "custom")
+}
+/// Value of an integer literal, e.g., `1_000u64`
+fn int_literal(lit: &ra_ap_syntax::ast::Literal) -> Option<u128> {
+    use ra_ap_syntax::AstNode;
+    let text = lit.syntax().text().to_string().replace('_', "");
+    // strip the type suffix
+    let digits = match text.find(['u', 'i']) {
+        Some(end) => &text[..end],
+        None => &text,
+    };
+    match digits.strip_prefix("0x") {
+        Some(hex) => u128::from_str_radix(hex, 16).ok(),
+        None => digits.parse().ok(),
+    }
+}
+/// Length of a fixed-size array type, e.g., 16 for `[[u32; 256]; 16]`
+fn array_len(ty: &str) -> Option<u128> {
+    let (_, len) = ty.strip_suffix(']')?.rsplit_once("; ")?;
+    len.parse().ok()
+}
+/// Upper bound of an index expression: a literal, a mask with `&`, or a remainder by a literal
+fn index_bound(expr: &ra_ap_syntax::ast::Expr) -> Option<u128> {
+    use ra_ap_syntax::ast::{self, ArithOp, BinaryOp};
+    match expr {
+        ast::Expr::Literal(lit) => int_literal(lit),
+        ast::Expr::ParenExpr(e) => index_bound(&e.expr()?),
+        ast::Expr::CastExpr(e) => index_bound(&e.expr()?),
+        ast::Expr::BinExpr(e) => match e.op_kind()? {
+            BinaryOp::ArithOp(ArithOp::BitAnd) => {
+                let lhs = e.lhs().and_then(|lhs| index_bound(&lhs));
+                let rhs = e.rhs().and_then(|rhs| index_bound(&rhs));
+                lhs.into_iter().chain(rhs).min()
+            }
+            BinaryOp::ArithOp(ArithOp::Rem) => index_bound(&e.rhs()?)?.checked_sub(1),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+/// Bit width of an integer type. `usize` is 32 bits on wasm32, and at least that elsewhere.
+fn int_width(ty: &str) -> Option<u128> {
+    match ty {
+        "u8" | "i8" => Some(8),
+        "u16" | "i16" => Some(16),
+        "u32" | "i32" | "usize" | "isize" => Some(32),
+        "u64" | "i64" => Some(64),
+        "u128" | "i128" => Some(128),
+        _ => None,
+    }
+}
+/// Functions in the sysroot are not traced
+fn is_sysroot(krate: Crate, db: &RootDatabase) -> bool {
+    use ra_ap_base_db::CrateOrigin;
+    matches!(
+        krate.origin(db),
+        CrateOrigin::Rustc { .. } | CrateOrigin::Lang(_)
+    )
+}
+fn is_proc_macro(krate: Crate, db: &RootDatabase) -> bool {
+    use ra_ap_base_db::SourceDatabase;
+    db.crate_graph()[CrateId::from(krate)].is_proc_macro
+}
+/// Guard types that must not be held across an `.await`
+const GUARD_TYPES: [&str; 5] = [
+    "Ref<",
+    "RefMut<",
+    "MutexGuard<",
+    "RwLockReadGuard<",
+    "RwLockWriteGuard<",
+];
+/// The node is inside a closure or an async block nested in `outer`
+fn in_nested_body(node: &SyntaxNode, outer: &SyntaxNode) -> bool {
+    use ra_ap_syntax::ast::{self, AstNode};
+    node.ancestors().take_while(|n| n != outer).any(|n| {
+        ast::ClosureExpr::can_cast(n.kind())
+            || ast::BlockExpr::cast(n).is_some_and(|b| b.async_token().is_some())
+    })
+}
+/// `drop(name)` or `std::mem::drop(name)`
+fn is_drop(call: &ra_ap_syntax::ast::CallExpr, name: &str) -> bool {
+    use ra_ap_syntax::ast::{self, HasArgList};
+    let Some(ast::Expr::PathExpr(callee)) = call.expr() else {
+        return false;
+    };
+    let is_drop = callee
+        .path()
+        .and_then(|p| p.segment())
+        .is_some_and(|s| s.to_string() == "drop");
+    let args: Vec<_> = call.arg_list().into_iter().flat_map(|l| l.args()).collect();
+    is_drop && args.len() == 1 && args[0].to_string() == name
+}
+/// Types with interior mutability that are commonly used for global state
+fn global_state_type(ty: &str) -> bool {
+    const TYPES: [&str; 10] = [
+        "Cell<",
+        "RefCell<",
+        "UnsafeCell<",
+        "OnceCell<",
+        "OnceLock<",
+        "Lazy<",
+        "LazyLock<",
+        "Mutex<",
+        "RwLock<",
+        "Atomic",
+    ];
+    TYPES.iter().any(|t| ty.starts_with(t))
+}
@@ -0,0 +1,62 @@
+use crate::audit::{Finding, Kind};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Stable identity of a finding across runs. Source locations and call paths are
+/// intentionally left out, as they change with unrelated edits.
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+struct Fingerprint {
+    kind: Kind,
+    function: String,
+    #[serde(rename = "crate")]
+    krate: String,
+    /// `None` matches any version of the crate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct Baseline {
+    findings: BTreeSet<Fingerprint>,
+}
+impl Baseline {
+    pub fn new(findings: &[Finding], ignore_version: bool) -> Self {
+        let findings = findings
+            .iter()
+            .filter(|f| !f.is_suppressed())
+            .map(|f| Fingerprint {
+                kind: f.kind,
+                function: f.function.clone(),
+                krate: f.krate.clone(),
+                version: if ignore_version {
+                    None
+                } else {
+                    f.version.clone()
+                },
+            })
+            .collect();
+        Self { findings }
+    }
+    pub fn len(&self) -> usize {
+        self.findings.len()
+    }
+    pub fn contains(&self, finding: &Finding) -> bool {
+        self.findings.iter().any(|fp| {
+            fp.kind == finding.kind
+                && fp.function == finding.function
+                && fp.krate == finding.krate
+                && (fp.version.is_none() || fp.version == finding.version)
+        })
+    }
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Cannot parse baseline {}: {e}", path.display()))
+    }
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, content + "\n")?;
+        Ok(())
+    }
+}
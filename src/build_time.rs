@@ -0,0 +1,335 @@
+use crate::audit::{Finding, Kind, Location};
+use crate::baseline::Baseline;
+use crate::policy::AllowRule;
+use console::style;
+use indicatif::MultiProgress;
+use ra_ap_ide::LineIndex;
+use ra_ap_project_model::{CargoWorkspace, TargetKind};
+use ra_ap_syntax::ast::{self, HasArgList, HasAttrs, HasName};
+use ra_ap_syntax::{AstNode, AstToken, Edition, SourceFile, SyntaxNode};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Code that runs on the developer machine at build time
+#[derive(Clone, Debug, Serialize)]
+pub struct Target {
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub version: String,
+    /// "build script" or "proc-macro"
+    pub kind: &'static str,
+    pub root: String,
+}
+impl Target {
+    pub fn message(&self) -> String {
+        format!(
+            "{:>12} {} {}@{} ({})",
+            style("Build").cyan().bold(),
+            self.kind,
+            self.krate,
+            self.version,
+            self.root
+        )
+    }
+}
+/// Syntactic scan of build scripts and proc-macro crates. Build scripts are not part of the
+/// crate graph, so paths are matched by name instead of resolved.
+pub struct Scanner<'a> {
+    bars: &'a MultiProgress,
+    is_verbose: bool,
+    whitelist: &'a [String],
+    pub targets: Vec<Target>,
+    pub findings: Vec<Finding>,
+    pub print_findings: bool,
+    pub allow_rules: &'a [AllowRule],
+    pub baseline: Option<&'a Baseline>,
+    pub baselined: Vec<Finding>,
+}
+/// Path suffixes that spawn processes or access the network
+const PROCESS_APIS: [&str; 2] = ["Command::new", "process::Command"];
+const NETWORK_APIS: [&str; 9] = [
+    "TcpStream::connect",
+    "TcpListener::bind",
+    "UdpSocket::bind",
+    "reqwest::",
+    "ureq::",
+    "curl::",
+    "attohttpc::",
+    "minreq::",
+    "git2::",
+];
+/// Path suffixes that write to the filesystem
+const WRITE_APIS: [&str; 10] = [
+    "fs::write",
+    "File::create",
+    "fs::create_dir",
+    "fs::create_dir_all",
+    "fs::remove_file",
+    "fs::remove_dir",
+    "fs::remove_dir_all",
+    "fs::copy",
+    "fs::rename",
+    "OpenOptions::new",
+];
+impl<'a> Scanner<'a> {
+    pub fn new(bars: &'a MultiProgress, is_verbose: bool, whitelist: &'a [String]) -> Self {
+        Scanner {
+            bars,
+            is_verbose,
+            whitelist,
+            targets: Vec::new(),
+            findings: Vec::new(),
+            print_findings: true,
+            allow_rules: &[],
+            baseline: None,
+            baselined: Vec::new(),
+        }
+    }
+    pub fn scan(&mut self, cargo: &CargoWorkspace) {
+        for pkg in cargo.packages() {
+            let package = &cargo[pkg];
+            for target in &package.targets {
+                let target = &cargo[*target];
+                let kind = match target.kind {
+                    TargetKind::BuildScript => "build script",
+                    TargetKind::Lib {
+                        is_proc_macro: true,
+                    } => "proc-macro",
+                    _ => continue,
+                };
+                let root: &Path = target.root.as_ref();
+                let target = Target {
+                    krate: package.name.replace('-', "_"),
+                    version: package.version.to_string(),
+                    kind,
+                    root: relative(root),
+                };
+                if self.print_findings {
+                    self.print(target.message());
+                }
+                // Only the module tree of the target is scanned, not everything next to it
+                let package_dir: &Path = package.manifest.parent().as_ref();
+                let mut files = Vec::new();
+                let dir = root.parent().unwrap_or(root);
+                module_files(root, dir, None, package_dir, &mut files);
+                for (file, module, source, index) in files {
+                    self.scan_file(&target, &file, module, &source, &index);
+                }
+                self.targets.push(target);
+            }
+        }
+    }
+    fn scan_file(
+        &mut self,
+        target: &Target,
+        file: &Path,
+        module: Option<String>,
+        source: &SourceFile,
+        index: &LineIndex,
+    ) {
+        for path_expr in source
+            .syntax()
+            .descendants()
+            .filter_map(ast::PathExpr::cast)
+        {
+            let Some(path) = path_expr.path() else {
+                continue;
+            };
+            let path = path.to_string();
+            let detail = if let Some(api) = PROCESS_APIS.iter().find(|api| path.ends_with(*api)) {
+                format!("spawns a process via `{api}`")
+            } else if let Some(api) = NETWORK_APIS.iter().find(|api| path.contains(*api)) {
+                format!("accesses the network via `{api}`")
+            } else if let Some(api) = WRITE_APIS.iter().find(|api| path.ends_with(*api)) {
+                if writes_to_out_dir(path_expr.syntax()) {
+                    continue;
+                }
+                format!("writes outside OUT_DIR via `{api}`")
+            } else {
+                continue;
+            };
+            let func = path_expr
+                .syntax()
+                .ancestors()
+                .find_map(ast::Fn::cast)
+                .and_then(|f| f.name())
+                .map(|name| name.text().to_string())
+                .unwrap_or_else(|| "<item>".to_string());
+            let function = match &module {
+                Some(module) => format!("{}::{module}::{func}", target.krate),
+                None => format!("{}::{func}", target.krate),
+            };
+            let range = path_expr.syntax().text_range();
+            let start = index.line_col(range.start());
+            let end = index.line_col(range.end());
+            let location = Location {
+                file: relative(file),
+                line: start.line + 1,
+                column: start.col + 1,
+                end_line: end.line + 1,
+                end_column: end.col + 1,
+            };
+            self.report(target, function, detail, location);
+        }
+    }
+    fn report(&mut self, target: &Target, function: String, detail: String, location: Location) {
+        let mut finding = Finding {
+            kind: Kind::BuildTime,
+            function,
+            krate: target.krate.clone(),
+            version: Some(target.version.clone()),
+            whitelisted: self.whitelist.iter().any(|w| {
+                let w = w.replace('-', "_");
+                w == target.krate || w == format!("{}@{}", target.krate, target.version)
+            }),
+            endpoint: None,
+            justification: None,
+            detail: Some(detail),
+            path: Vec::new(),
+            location: Some(location),
+            target: None,
+        };
+        finding.justification = self
+            .allow_rules
+            .iter()
+            .find(|rule| rule.matches(&finding))
+            .map(|rule| rule.justification.clone());
+        if self.baseline.is_some_and(|b| b.contains(&finding)) {
+            self.baselined.push(finding);
+            return;
+        }
+        if self.print_findings && !finding.is_suppressed() {
+            self.print(finding.message());
+        }
+        self.findings.push(finding);
+    }
+    fn print(&self, msg: impl std::convert::AsRef<str> + std::fmt::Display) {
+        if self.is_verbose {
+            log::error!("{msg}");
+        } else {
+            self.bars.println(msg).unwrap();
+        }
+    }
+}
+/// The statement mentions OUT_DIR, directly or through the local bindings it uses
+fn writes_to_out_dir(node: &SyntaxNode) -> bool {
+    let Some(call) = node.parent().and_then(ast::CallExpr::cast) else {
+        // `OpenOptions::new().write(true).open(path)`
+        let stmt = node.ancestors().find(|n| ast::Stmt::can_cast(n.kind()));
+        return stmt.is_some_and(|stmt| mentions_out_dir(&stmt, 0));
+    };
+    call.arg_list()
+        .and_then(|list| list.args().next())
+        .is_some_and(|arg| mentions_out_dir(arg.syntax(), 0))
+}
+fn mentions_out_dir(node: &SyntaxNode, depth: usize) -> bool {
+    if node.to_string().to_lowercase().contains("out_dir") {
+        return true;
+    }
+    if depth > 4 {
+        return false;
+    }
+    // Follow `let dest = Path::new(&out_dir).join(..)` in the enclosing function
+    let Some(body) = node.ancestors().find_map(ast::Fn::cast) else {
+        return false;
+    };
+    let names: Vec<_> = node
+        .descendants()
+        .filter_map(ast::NameRef::cast)
+        .map(|n| n.text().to_string())
+        .collect();
+    body.syntax()
+        .descendants()
+        .filter_map(ast::LetStmt::cast)
+        .filter(|stmt| match stmt.pat() {
+            Some(ast::Pat::IdentPat(pat)) => pat
+                .name()
+                .is_some_and(|n| names.iter().any(|name| *name == n.text())),
+            _ => false,
+        })
+        .filter_map(|stmt| stmt.initializer())
+        .any(|init| mentions_out_dir(init.syntax(), depth + 1))
+}
+type ModuleFile = (PathBuf, Option<String>, SourceFile, LineIndex);
+/// Files of the module tree rooted at `file`, following `mod foo;` and `#[path]` declarations.
+/// `dir` is where the child modules of `file` live. Files under `target/` are never scanned.
+fn module_files(
+    file: &Path,
+    dir: &Path,
+    module: Option<String>,
+    package_dir: &Path,
+    res: &mut Vec<ModuleFile>,
+) {
+    if file
+        .strip_prefix(package_dir)
+        .is_ok_and(|p| p.starts_with("target"))
+        || res.iter().any(|(f, ..)| f == file)
+    {
+        return;
+    }
+    let Ok(text) = std::fs::read_to_string(file) else {
+        return;
+    };
+    let source = SourceFile::parse(&text, Edition::CURRENT).tree();
+    let file_dir = file.parent().unwrap_or(dir).to_path_buf();
+    res.push((
+        file.to_path_buf(),
+        module.clone(),
+        source.clone(),
+        LineIndex::new(&text),
+    ));
+    for decl in source.syntax().descendants().filter_map(ast::Module::cast) {
+        if decl.item_list().is_some() {
+            continue;
+        }
+        let Some(name) = decl.name().map(|n| n.text().to_string()) else {
+            continue;
+        };
+        // inline modules enclosing the declaration, outermost first
+        let mut inline: Vec<_> = decl
+            .syntax()
+            .ancestors()
+            .skip(1)
+            .filter_map(ast::Module::cast)
+            .filter_map(|m| m.name().map(|n| n.text().to_string()))
+            .collect();
+        inline.reverse();
+        let mut child_module: Vec<_> = module.iter().cloned().chain(inline.clone()).collect();
+        child_module.push(name.clone());
+        let child_module = Some(child_module.join("::"));
+        let parent_dir = inline.iter().fold(dir.to_path_buf(), |d, m| d.join(m));
+        if let Some(path) = path_attr(&decl) {
+            let base = inline.iter().fold(file_dir.clone(), |d, m| d.join(m));
+            let child = base.join(path);
+            let child_dir = child.parent().unwrap_or(&base).to_path_buf();
+            module_files(&child, &child_dir, child_module, package_dir, res);
+            continue;
+        }
+        let flat = parent_dir.join(format!("{name}.rs"));
+        let nested = parent_dir.join(&name).join("mod.rs");
+        let child = if flat.is_file() { flat } else { nested };
+        module_files(
+            &child,
+            &parent_dir.join(&name),
+            child_module,
+            package_dir,
+            res,
+        );
+    }
+}
+/// `#[path = "foo.rs"] mod foo;`
+fn path_attr(decl: &ast::Module) -> Option<String> {
+    decl.attrs()
+        .filter(|attr| attr.simple_name().is_some_and(|name| name == "path"))
+        .find_map(|attr| match attr.expr()? {
+            ast::Expr::Literal(lit) => ast::String::cast(lit.token())?
+                .value()
+                .ok()
+                .map(|v| v.to_string()),
+            _ => None,
+        })
+}
+fn relative(path: &Path) -> String {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    path.strip_prefix(cwd).unwrap_or(path).display().to_string()
+}
@@ -0,0 +1,96 @@
+use crate::audit::Output;
+use crate::utils::crate_name;
+use crate::Options;
+use anyhow::Result;
+use ra_ap_base_db::CrateOrigin;
+use ra_ap_hir::Crate;
+use ra_ap_ide::RootDatabase;
+use ra_ap_project_model::CargoWorkspace;
+use std::path::{Path, PathBuf};
+
+/// Per-crate scan results under `target/canister-cache`, keyed on `Cargo.lock`, features,
+/// target, cfgs, whitelist and toolchain. Only registry and git dependencies are cached, as local crates
+/// can change without touching `Cargo.lock`.
+///
+/// Only the `ScanExports` output is cached. Loading the project still runs build scripts and
+/// proc macros every time, and tracing with `--trace-functions` is never cached.
+pub struct Cache {
+    dir: PathBuf,
+}
+impl Cache {
+    pub fn new(cargo: &CargoWorkspace, options: &Options, whitelist: &[String]) -> Result<Self> {
+        let root: &std::path::Path = cargo.workspace_root().as_ref();
+        let lockfile = std::fs::read_to_string(root.join("Cargo.lock")).unwrap_or_default();
+        let rustc = std::process::Command::new("rustc").arg("-V").output()?;
+        let mut features = options.features.clone();
+        features.sort();
+        let mut cfgs = options.cfg.clone();
+        cfgs.sort();
+        let key = format!(
+            "{}\n{}\n{}\n{:?}\n{}\n{:?}\n{:?}\n{:?}\n{}",
+            env!("CARGO_PKG_VERSION"),
+            String::from_utf8_lossy(&rustc.stdout),
+            lockfile,
+            features,
+            options.all_features,
+            options.no_default_features,
+            options.target,
+            cfgs,
+            whitelist.join(",")
+        );
+        let target: &std::path::Path = cargo.target_directory().as_ref();
+        let cache = target.join("canister-cache");
+        let dir = cache.join(format!("{:016x}", fxhash::hash64(&key)));
+        std::fs::create_dir_all(&dir)?;
+        // the marker's mtime records when the directory was last used
+        std::fs::write(dir.join(LAST_USED), "")?;
+        prune(&cache);
+        Ok(Cache { dir })
+    }
+    fn path(&self, db: &RootDatabase, krate: Crate) -> Option<PathBuf> {
+        if !matches!(krate.origin(db), CrateOrigin::Library { .. }) {
+            return None;
+        }
+        let name = crate_name(krate, db);
+        let version = krate.version(db)?;
+        // the same crate can be resolved with different features by different workspaces
+        let cfg = fxhash::hash64(&format!("{:?}", krate.cfg(db)));
+        Some(self.dir.join(format!("{name}-{version}-{cfg:016x}.json")))
+    }
+    pub fn get(&self, db: &RootDatabase, krate: Crate) -> Option<Output> {
+        let content = std::fs::read_to_string(self.path(db, krate)?).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+    /// Results are stored before allow rules and baselines are applied
+    pub fn put(&self, db: &RootDatabase, krate: Crate, output: &Output) -> Result<()> {
+        if let Some(path) = self.path(db, krate) {
+            // concurrent runs never read a partially written file
+            let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+            std::fs::write(&tmp, serde_json::to_string(output)?)?;
+            std::fs::rename(tmp, path)?;
+        }
+        Ok(())
+    }
+}
+const LAST_USED: &str = ".last-used";
+/// Cache directories kept, e.g., for switching between targets or feature sets
+const KEEP: usize = 8;
+/// Removes all but the most recently used cache directories
+fn prune(cache: &Path) {
+    let Ok(entries) = std::fs::read_dir(cache) else {
+        return;
+    };
+    let mut dirs: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let used = std::fs::metadata(entry.path().join(LAST_USED))
+                .and_then(|m| m.modified())
+                .ok();
+            (used, entry.path())
+        })
+        .collect();
+    dirs.sort_by(|a, b| b.0.cmp(&a.0));
+    for (_, dir) in dirs.into_iter().skip(KEEP) {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
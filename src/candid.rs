@@ -77,13 +77,26 @@ impl<'a> Builder<'a> {
 struct CDKAttr {
     mode: String,
 }
-fn get_cdk_attr(func: &hir::Function, db: &RootDatabase) -> Option<CDKAttr> {
+/// CDK attributes that export a function as a canister entry point
+const ENTRY_POINT_ATTRS: [&str; 7] = [
+    "query",
+    "update",
+    "init",
+    "pre_upgrade",
+    "post_upgrade",
+    "heartbeat",
+    "inspect_message",
+];
+/// Returns the CDK attribute name if the function is a canister entry point
+pub fn get_entry_point_attr(func: &hir::Function, db: &RootDatabase) -> Option<String> {
     use ra_ap_hir::HasAttrs;
     let attrs = func.attrs(db);
-    let cdk = attrs.iter().find(|attr| {
-        let attr = attr.path().segments().last().map(|s| s.to_smol_str());
-        attr == Some("update".into()) || attr == Some("query".into()) || attr == Some("init".into())
-    })?;
-    let mode = cdk.path().segments().last()?.as_str().unwrap().to_string();
-    Some(CDKAttr { mode })
+    attrs.iter().find_map(|attr| {
+        let name = attr.path().segments().last()?.as_str()?.to_string();
+        ENTRY_POINT_ATTRS.contains(&name.as_str()).then_some(name)
+    })
+}
+fn get_cdk_attr(func: &hir::Function, db: &RootDatabase) -> Option<CDKAttr> {
+    let mode = get_entry_point_attr(func, db)?;
+    matches!(mode.as_str(), "update" | "query" | "init").then_some(CDKAttr { mode })
 }
@@ -0,0 +1,244 @@
+use crate::audit::{Finding, Kind};
+use anyhow::Result;
+use console::style;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A copy of the workspace at an old revision, or with an old `Cargo.lock`, removed on drop.
+/// The user's checkout is never modified, so an interrupted run leaves it intact.
+pub struct OldWorkspace {
+    dir: PathBuf,
+    /// Git repository that owns the worktree at `dir`
+    repo: Option<PathBuf>,
+    /// Project root inside the copy, matching `--manifest-path`
+    pub manifest_path: PathBuf,
+}
+impl OldWorkspace {
+    /// `rev` is either a lockfile path or a git revision
+    pub fn new(manifest_path: &Path, rev: &str) -> Result<Self> {
+        let root = workspace_root(manifest_path)?;
+        let relative = manifest_path
+            .canonicalize()?
+            .strip_prefix(&root)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let dir = std::env::temp_dir().join(format!("canister-diff-{}", std::process::id()));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+        let old = if Path::new(rev).is_file() {
+            let lockfile = std::fs::read_to_string(rev)?;
+            let old = OldWorkspace {
+                dir: dir.clone(),
+                repo: None,
+                manifest_path: dir.join(&relative),
+            };
+            copy_dir(&root, &dir)?;
+            std::fs::write(dir.join("Cargo.lock"), lockfile)?;
+            old
+        } else {
+            let repo = git(&root, &["rev-parse", "--show-toplevel"])?;
+            let repo = PathBuf::from(repo.trim()).canonicalize()?;
+            git(
+                &repo,
+                &["worktree", "add", "--detach", &dir.to_string_lossy(), rev],
+            )?;
+            let old_root = dir.join(root.strip_prefix(&repo).unwrap_or(Path::new("")));
+            OldWorkspace {
+                dir,
+                repo: Some(repo),
+                manifest_path: old_root.join(&relative),
+            }
+        };
+        // cargo metadata re-resolves a stale lockfile silently, so check it upfront
+        let output = Command::new("cargo")
+            .args(["metadata", "--locked", "--format-version", "1"])
+            .current_dir(&old.manifest_path)
+            .stdout(std::process::Stdio::null())
+            .output()?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Cargo.lock at {rev} does not match its manifests: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+        Ok(old)
+    }
+}
+impl Drop for OldWorkspace {
+    fn drop(&mut self) {
+        let res = match &self.repo {
+            Some(repo) => git(
+                repo,
+                &["worktree", "remove", "--force", &self.dir.to_string_lossy()],
+            )
+            .map(|_| ()),
+            None => std::fs::remove_dir_all(&self.dir).map_err(Into::into),
+        };
+        if let Err(e) = res {
+            log::error!("Cannot remove {}: {e}", self.dir.display());
+        }
+    }
+}
+fn workspace_root(manifest_path: &Path) -> Result<PathBuf> {
+    let output = Command::new("cargo")
+        .args(["locate-project", "--workspace", "--message-format", "plain"])
+        .current_dir(manifest_path)
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "cargo locate-project failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    let manifest = PathBuf::from(String::from_utf8(output.stdout)?.trim());
+    Ok(manifest.parent().unwrap().canonicalize()?)
+}
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+/// Copy the workspace sources, skipping build outputs and git metadata
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == "target" || name == ".git" {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to.join(&name))?;
+        } else {
+            std::fs::copy(entry.path(), to.join(&name))?;
+        }
+    }
+    Ok(())
+}
+
+pub type Key = (Kind, String, String, Option<String>);
+pub fn key(f: &Finding) -> Key {
+    (
+        f.kind,
+        f.function.clone(),
+        f.krate.clone(),
+        f.version.clone(),
+    )
+}
+/// The first finding of each key
+pub fn unique<'a>(findings: impl IntoIterator<Item = &'a Finding>) -> Vec<Finding> {
+    let mut seen = BTreeSet::new();
+    findings
+        .into_iter()
+        .filter(|f| seen.insert(key(f)))
+        .cloned()
+        .collect()
+}
+
+#[derive(Serialize)]
+pub struct Diff {
+    pub added: Vec<Finding>,
+    pub removed: Vec<Finding>,
+    pub unchanged: Vec<Finding>,
+    /// Old and new versions of each crate with findings
+    #[serde(skip)]
+    versions: BTreeMap<String, (BTreeSet<String>, BTreeSet<String>)>,
+}
+/// A dependency bump without code changes keeps the findings of the crate unchanged
+fn diff_key(f: &Finding) -> (Kind, String, String) {
+    (f.kind, f.function.clone(), f.krate.clone())
+}
+impl Diff {
+    /// Compare unsuppressed findings by kind, function and crate, regardless of the crate version.
+    pub fn new(old: Vec<Finding>, new: Vec<Finding>) -> Self {
+        let mut versions: BTreeMap<String, (BTreeSet<String>, BTreeSet<String>)> = BTreeMap::new();
+        let old: BTreeMap<_, _> = old
+            .into_iter()
+            .filter(|f| !f.is_suppressed())
+            .inspect(|f| {
+                let entry = versions.entry(f.krate.clone()).or_default();
+                entry.0.extend(f.version.clone());
+            })
+            .map(|f| (diff_key(&f), f))
+            .collect();
+        let new: BTreeMap<_, _> = new
+            .into_iter()
+            .filter(|f| !f.is_suppressed())
+            .inspect(|f| {
+                let entry = versions.entry(f.krate.clone()).or_default();
+                entry.1.extend(f.version.clone());
+            })
+            .map(|f| (diff_key(&f), f))
+            .collect();
+        let mut diff = Diff {
+            added: Vec::new(),
+            removed: Vec::new(),
+            unchanged: Vec::new(),
+            versions,
+        };
+        for (k, f) in &new {
+            if old.contains_key(k) {
+                diff.unchanged.push(f.clone());
+            } else {
+                diff.added.push(f.clone());
+            }
+        }
+        for (k, f) in old {
+            if !new.contains_key(&k) {
+                diff.removed.push(f);
+            }
+        }
+        diff
+    }
+    /// Print the diff grouped by crate, with the old and new versions
+    pub fn print(&self) {
+        type Group<'a> = (Vec<&'a Finding>, Vec<&'a Finding>, usize);
+        let mut crates: BTreeMap<&str, Group> = BTreeMap::new();
+        for f in &self.added {
+            crates.entry(&f.krate).or_default().0.push(f);
+        }
+        for f in &self.removed {
+            crates.entry(&f.krate).or_default().1.push(f);
+        }
+        for f in &self.unchanged {
+            crates.entry(&f.krate).or_default().2 += 1;
+        }
+        for (name, (added, removed, unchanged)) in crates {
+            if added.is_empty() && removed.is_empty() {
+                continue;
+            }
+            let join = |v: &BTreeSet<String>| v.iter().cloned().collect::<Vec<_>>().join(", ");
+            let version = match self.versions.get(name) {
+                Some((old, new)) if !old.is_empty() && !new.is_empty() && old != new => {
+                    format!("{} -> {}", join(old), join(new))
+                }
+                Some((old, new)) if new.is_empty() => join(old),
+                Some((_, new)) => join(new),
+                None => String::new(),
+            };
+            println!(
+                "{} {}: {} added, {} removed, {} unchanged",
+                style(name).bold(),
+                version,
+                added.len(),
+                removed.len(),
+                unchanged
+            );
+            for f in added {
+                println!("  {} {}", style("+").green().bold(), f.message());
+            }
+            for f in removed {
+                println!("  {} {}", style("-").red().bold(), f.message());
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use crate::audit::{Finding, Kind};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Serialize)]
+struct Node {
+    #[serde(rename = "crate")]
+    krate: String,
+    version: Option<String>,
+}
+/// Call graph collected while tracing functions
+#[derive(Clone, Default)]
+pub struct CallGraph {
+    nodes: BTreeMap<String, Node>,
+    edges: BTreeSet<(String, String)>,
+}
+impl CallGraph {
+    pub fn add_node(&mut self, function: String, krate: String, version: Option<String>) {
+        self.nodes.insert(function, Node { krate, version });
+    }
+    pub fn add_edge(&mut self, caller: &str, callee: String) {
+        self.edges.insert((caller.to_string(), callee));
+    }
+    fn findings(findings: &[Finding]) -> BTreeMap<&str, BTreeSet<Kind>> {
+        let mut res: BTreeMap<_, BTreeSet<_>> = BTreeMap::new();
+        for f in findings.iter().filter(|f| !f.is_suppressed()) {
+            res.entry(f.function.as_str()).or_default().insert(f.kind);
+        }
+        res
+    }
+    /// Graphviz format with crates as clusters, and functions with findings as colored nodes
+    pub fn to_dot(&self, findings: &[Finding]) -> String {
+        let findings = Self::findings(findings);
+        let mut crates: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (name, node) in &self.nodes {
+            crates
+                .entry((&node.krate, &node.version))
+                .or_default()
+                .push(name);
+        }
+        let mut res = String::from("digraph calls {\n  rankdir=LR;\n  node [shape=box];\n");
+        for (i, ((krate, version), names)) in crates.into_iter().enumerate() {
+            let label = match version {
+                Some(version) => format!("{krate} {version}"),
+                None => krate.to_string(),
+            };
+            res.push_str(&format!(
+                "  subgraph cluster_{i} {{\n    label={};\n",
+                quote(&label)
+            ));
+            for name in names {
+                let attrs = match findings.get(name.as_str()) {
+                    Some(kinds) => {
+                        let color = if kinds.contains(&Kind::Import)
+                            || kinds.contains(&Kind::InvalidImport)
+                        {
+                            "lightcoral"
+                        } else {
+                            "khaki"
+                        };
+                        let tooltip: Vec<_> = kinds.iter().map(|k| format!("{k:?}")).collect();
+                        format!(
+                            " [style=filled, fillcolor={color}, tooltip={}]",
+                            quote(&tooltip.join(", "))
+                        )
+                    }
+                    None => String::new(),
+                };
+                res.push_str(&format!("    {}{attrs};\n", quote(name)));
+            }
+            res.push_str("  }\n");
+        }
+        for (caller, callee) in &self.edges {
+            res.push_str(&format!("  {} -> {};\n", quote(caller), quote(callee)));
+        }
+        res.push_str("}\n");
+        res
+    }
+    /// Adjacency list in JSON
+    pub fn to_json(&self, findings: &[Finding]) -> Value {
+        let findings = Self::findings(findings);
+        let mut callees: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (caller, callee) in &self.edges {
+            callees.entry(caller).or_default().push(callee);
+        }
+        let nodes: BTreeMap<_, _> = self
+            .nodes
+            .iter()
+            .map(|(name, node)| {
+                let kinds = findings.get(name.as_str()).cloned().unwrap_or_default();
+                let node = json!({
+                    "crate": node.krate,
+                    "version": node.version,
+                    "findings": kinds,
+                    "callees": callees.get(name.as_str()).cloned().unwrap_or_default(),
+                });
+                (name, node)
+            })
+            .collect();
+        json!({ "nodes": nodes })
+    }
+}
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
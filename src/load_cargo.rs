@@ -1,15 +1,17 @@
+use crate::metadata::Metadata;
 use crate::utils::create_bar;
 use crate::Options;
 use anyhow::Result;
 use indicatif::{MultiProgress, ProgressBar};
 use ra_ap_base_db::CrateId;
+use ra_ap_cfg::{CfgAtom, CfgDiff};
 use ra_ap_hir::Crate;
 use ra_ap_ide::RootDatabase;
 use ra_ap_load_cargo::{load_workspace, LoadCargoConfig, ProcMacroServerChoice};
 use ra_ap_paths::{AbsPathBuf, Utf8PathBuf};
 use ra_ap_project_model::{
-    CargoConfig, CargoFeatures, CargoWorkspace, ProjectManifest, ProjectWorkspace,
-    ProjectWorkspaceKind, TargetData, TargetKind,
+    CargoConfig, CargoFeatures, CargoWorkspace, CfgOverrides, Package, PackageData,
+    ProjectManifest, ProjectWorkspace, ProjectWorkspaceKind, TargetData, TargetKind,
 };
 use ra_ap_vfs::Vfs;
 use std::path::Path;
@@ -17,28 +19,158 @@ use std::path::Path;
 pub fn load_cargo_project(
     options: &Options,
     bars: &MultiProgress,
-) -> Result<(CargoWorkspace, RootDatabase, Vfs, TargetData)> {
+) -> Result<(CargoWorkspace, RootDatabase, Vfs, Vec<TargetData>, Metadata)> {
     let bar = create_bar(bars, "Loading project...");
-    let path = options.manifest_path.as_path();
-    let cargo_config = cargo_config(options);
     let load_config = load_config(options);
     let pb = create_bar(bars, "Building...");
-    let mut ws = load_project_workspace(path, &cargo_config, &pb)?;
+    let (mut ws, cargo, targets, metadata, cargo_config) = load_metadata(options, &pb)?;
     if load_config.load_out_dirs_from_check {
         let build_scripts = ws.run_build_scripts(&cargo_config, &|msg| {
             pb.set_message(msg.to_string());
         })?;
         ws.set_build_scripts(build_scripts);
     }
-    let cargo = match &ws.kind {
-        ProjectWorkspaceKind::Cargo { cargo, .. } => cargo.clone(),
-        _ => return Err(anyhow::anyhow!("Not a cargo workspace")),
-    };
-    let target = find_package(&cargo, options.package.as_deref(), None)?;
     let (db, vfs, _proc) = load_workspace(ws, &cargo_config.extra_env, &load_config)?;
     pb.finish_and_clear();
     bar.finish();
-    Ok((cargo, db, vfs, target))
+    Ok((cargo, db, vfs, targets, metadata))
+}
+/// Runs only `cargo metadata`, without executing build scripts or proc macros
+pub fn load_cargo_metadata(
+    options: &Options,
+    bars: &MultiProgress,
+) -> Result<(CargoWorkspace, Vec<TargetData>, Metadata)> {
+    let bar = create_bar(bars, "Loading project...");
+    let (_, cargo, targets, metadata, _) = load_metadata(options, &bar)?;
+    bar.finish();
+    Ok((cargo, targets, metadata))
+}
+type Loaded = (
+    ProjectWorkspace,
+    CargoWorkspace,
+    Vec<TargetData>,
+    Metadata,
+    CargoConfig,
+);
+fn load_metadata(options: &Options, bar: &ProgressBar) -> Result<Loaded> {
+    let path = options.manifest_path.as_path();
+    let mut cargo_config = cargo_config(options)?;
+    let mut ws = load_project_workspace(path, &cargo_config, bar)?;
+    let mut cargo = cargo_workspace(&ws)?;
+    let mut targets = find_packages(&cargo, options)?;
+    let metadata = Metadata::load(&cargo, &targets)?;
+    // Features and target from the metadata change what cargo resolves
+    let config = self::cargo_config(&metadata.apply(options))?;
+    if config != cargo_config {
+        cargo_config = config;
+        ws = load_project_workspace(path, &cargo_config, bar)?;
+        cargo = cargo_workspace(&ws)?;
+        targets = find_packages(&cargo, options)?;
+    }
+    Ok((ws, cargo, targets, metadata, cargo_config))
+}
+fn cargo_workspace(ws: &ProjectWorkspace) -> Result<CargoWorkspace> {
+    match &ws.kind {
+        ProjectWorkspaceKind::Cargo { cargo, .. } => Ok(cargo.clone()),
+        _ => Err(anyhow::anyhow!("Not a cargo workspace")),
+    }
+}
+/// Target of a package to analyze
+#[derive(Clone, Copy)]
+enum Selection<'a> {
+    /// The library target, or the only binary target if there is no library
+    Default,
+    Lib,
+    Bin(&'a str),
+}
+/// Packages selected by `--workspace` or `--package`, sharing the same loaded database
+fn find_packages(cargo: &CargoWorkspace, options: &Options) -> Result<Vec<TargetData>> {
+    let selection = match (options.lib, &options.bin) {
+        (true, _) => Selection::Lib,
+        (_, Some(bin)) => Selection::Bin(bin),
+        _ => Selection::Default,
+    };
+    if options.workspace {
+        let targets = cargo
+            .packages()
+            .filter(|idx| cargo[*idx].is_member && is_cdylib(&cargo[*idx]))
+            .map(|idx| find_target(cargo, idx, selection))
+            .filter(|target| match target {
+                Ok(target) if std::fs::metadata(&target.root).is_err() => {
+                    log::warn!(
+                        "Cannot find crate root {}, skipping workspace member {}...",
+                        target.root,
+                        cargo[target.package].name
+                    );
+                    false
+                }
+                _ => true,
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if targets.is_empty() {
+            return Err(anyhow::anyhow!("No cdylib package found in the workspace"));
+        }
+        return Ok(targets);
+    }
+    if options.package.is_empty() {
+        return Ok(vec![find_package(cargo, None, None, selection)?]);
+    }
+    // Explicitly selected packages are never skipped
+    options
+        .package
+        .iter()
+        .map(|name| {
+            let target = find_package(cargo, Some(name), None, selection)?;
+            if std::fs::metadata(&target.root).is_err() {
+                return Err(anyhow::anyhow!(
+                    "Cannot find crate root {} of package {name}",
+                    target.root
+                ));
+            }
+            Ok(target)
+        })
+        .collect()
+}
+/// `overflow-checks` of a cargo profile in the workspace manifest, following `inherits`
+pub fn overflow_checks(cargo: &CargoWorkspace, profile: &str) -> bool {
+    let manifest = cargo.workspace_root().join("Cargo.toml");
+    let profiles = std::fs::read_to_string(&manifest)
+        .ok()
+        .and_then(|content| toml::from_str::<toml::Table>(&content).ok())
+        .and_then(|manifest| manifest.get("profile")?.as_table().cloned())
+        .unwrap_or_default();
+    let mut name = profile;
+    for _ in 0..8 {
+        let profile = profiles.get(name);
+        if let Some(checks) = profile
+            .and_then(|p| p.get("overflow-checks"))
+            .and_then(|v| v.as_bool())
+        {
+            return checks;
+        }
+        match profile
+            .and_then(|p| p.get("inherits"))
+            .and_then(|v| v.as_str())
+        {
+            Some(parent) => name = parent,
+            None => break,
+        }
+    }
+    // overflow checks are only enabled by default in debug builds
+    matches!(name, "dev" | "test")
+}
+fn is_cdylib(package: &PackageData) -> bool {
+    let Ok(content) = std::fs::read_to_string(&*package.manifest) else {
+        return false;
+    };
+    let Ok(manifest) = toml::from_str::<toml::Table>(&content) else {
+        return false;
+    };
+    manifest
+        .get("lib")
+        .and_then(|lib| lib.get("crate-type"))
+        .and_then(|types| types.as_array())
+        .is_some_and(|types| types.iter().any(|t| t.as_str() == Some("cdylib")))
 }
 
 fn load_project_workspace(
@@ -55,11 +187,48 @@ fn load_project_workspace(
         bar.set_message(msg.to_string());
     })
 }
-fn cargo_config(options: &Options) -> CargoConfig {
+/// `--cfg feature_x` or `--cfg key=value` for all crates
+fn cfg_overrides(cfgs: &[String]) -> Result<CfgOverrides> {
+    let atoms = cfgs
+        .iter()
+        .map(|cfg| {
+            let atom = match cfg.split_once('=') {
+                Some((key, value)) => CfgAtom::KeyValue {
+                    key: key.trim().into(),
+                    value: value.trim().trim_matches('"').into(),
+                },
+                None => CfgAtom::Flag(cfg.trim().into()),
+            };
+            match &atom {
+                CfgAtom::Flag(key) | CfgAtom::KeyValue { key, .. } if key.is_empty() => {
+                    Err(anyhow::anyhow!("Invalid --cfg {cfg}: missing name"))
+                }
+                _ => Ok(atom),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let global = CfgDiff::new(atoms.clone(), Vec::new()).ok_or_else(|| {
+        let duplicate = (0..atoms.len())
+            .find(|i| atoms[..*i].contains(&atoms[*i]))
+            .map_or("", |i| cfgs[i].as_str());
+        anyhow::anyhow!("Conflicting --cfg options: {duplicate} is given more than once")
+    })?;
+    Ok(CfgOverrides {
+        global,
+        ..Default::default()
+    })
+}
+fn cargo_config(options: &Options) -> Result<CargoConfig> {
     let mut config = CargoConfig {
-        target: Some("wasm32-unknown-unknown".to_string()),
+        target: Some(
+            options
+                .target
+                .clone()
+                .unwrap_or_else(|| "wasm32-unknown-unknown".to_string()),
+        ),
         // sysroot needs to present for proc macro expansion to work
         sysroot: Some(ra_ap_project_model::RustLibSource::Discover),
+        cfg_overrides: cfg_overrides(&options.cfg)?,
         ..Default::default()
     };
     config.features = if options.all_features {
@@ -70,7 +239,7 @@ fn cargo_config(options: &Options) -> CargoConfig {
             no_default_features: options.no_default_features,
         }
     };
-    config
+    Ok(config)
 }
 fn load_config(options: &Options) -> LoadCargoConfig {
     LoadCargoConfig {
@@ -83,6 +252,7 @@ fn find_package(
     cargo: &CargoWorkspace,
     name: Option<&str>,
     version: Option<&str>,
+    selection: Selection,
 ) -> Result<TargetData> {
     let packages: Vec<_> = cargo
         .packages()
@@ -121,27 +291,61 @@ fn find_package(
                 .map(|idx| cargo[idx].name.to_string())
                 .collect();
             return Err(anyhow::anyhow!(
-                "Multiple packages present in workspace, please select one via --package flag:\n{}",
+                "Multiple packages present in workspace, please select one via --package, or use --workspace:\n{}",
                 packages.join("\n")
             ));
         }
     }
-    let package_idx = packages[0];
-    let package = cargo[package_idx].clone();
-    let targets: Vec<_> = package
+    find_target(cargo, packages[0], selection)
+}
+fn find_target(
+    cargo: &CargoWorkspace,
+    package_idx: Package,
+    selection: Selection,
+) -> Result<TargetData> {
+    let package = &cargo[package_idx];
+    let libs: Vec<_> = package
+        .targets
+        .iter()
+        .map(|idx| &cargo[*idx])
+        .filter(|target| matches!(target.kind, TargetKind::Lib { .. }))
+        .collect();
+    let bins: Vec<_> = package
         .targets
         .iter()
-        .cloned()
-        .filter(|idx| matches!(&cargo[*idx].kind, TargetKind::Lib { .. }))
+        .map(|idx| &cargo[*idx])
+        .filter(|target| target.kind == TargetKind::Bin)
         .collect();
-    if targets.len() != 1 {
-        return Err(anyhow::anyhow!(
-            "No library target found for {}.",
-            package.name
-        ));
+    let target = match selection {
+        Selection::Bin(name) => bins.iter().find(|target| target.name == name),
+        Selection::Default if libs.is_empty() && bins.len() > 1 => {
+            let bins: Vec<_> = bins.iter().map(|target| target.name.as_str()).collect();
+            return Err(anyhow::anyhow!(
+                "Multiple binary targets found for {}, please select one via --bin:\n{}",
+                package.name,
+                bins.join("\n")
+            ));
+        }
+        Selection::Default if libs.is_empty() => bins.first(),
+        Selection::Default | Selection::Lib => libs.first(),
+    };
+    match target {
+        Some(target) => Ok((*target).clone()),
+        None => match selection {
+            Selection::Bin(name) => Err(anyhow::anyhow!(
+                "No binary target {name} found for {}.",
+                package.name
+            )),
+            Selection::Lib => Err(anyhow::anyhow!(
+                "No library target found for {}.",
+                package.name
+            )),
+            Selection::Default => Err(anyhow::anyhow!(
+                "No library or binary target found for {}.",
+                package.name
+            )),
+        },
     }
-    let target = cargo[targets[0]].clone();
-    Ok(target)
 }
 pub fn find_whitelisted_crates(
     ws: &CargoWorkspace,
@@ -154,7 +358,7 @@ pub fn find_whitelisted_crates(
         let parsed: Vec<_> = item.split('@').collect();
         let name = parsed[0];
         let version = parsed.get(1);
-        let target = match find_package(ws, Some(name), version.copied()) {
+        let target = match find_package(ws, Some(name), version.copied(), Selection::Lib) {
             Ok(target) => target,
             Err(e) => {
                 if e.to_string().starts_with("Cannot find package") {
@@ -171,29 +375,58 @@ pub fn find_whitelisted_crates(
     Ok(res)
 }
 pub fn find_crate(db: &RootDatabase, vfs: &Vfs, target: &TargetData) -> Result<Crate> {
-    let crates = Crate::all(db);
     let root_path = target.root.as_path();
-    let krate = crates.into_iter().find(|krate| {
-        let vfs_path = vfs.file_path(krate.root_file(db));
-        let crate_root_path = vfs_path.as_path().unwrap();
-        crate_root_path == root_path
-    });
-    krate.ok_or_else(|| anyhow::anyhow!("crate {} not found", target.name))
-}
-pub fn find_non_root_crates(db: &RootDatabase, vfs: &Vfs, target: &TargetData) -> Vec<Crate> {
+    let crates: Vec<_> = Crate::all(db)
+        .into_iter()
+        .filter(|krate| {
+            let vfs_path = vfs.file_path(krate.root_file(db));
+            let crate_root_path = vfs_path.as_path().unwrap();
+            crate_root_path == root_path
+        })
+        .collect();
+    // The lib and bin targets can share the same root file, so prefer the crate named after the target
+    let krate = crates
+        .iter()
+        .find(|krate| {
+            krate
+                .display_name(db)
+                .is_some_and(|name| name.canonical_name() == target.name)
+        })
+        .or(crates.first());
+    krate
+        .copied()
+        .ok_or_else(|| anyhow::anyhow!("crate {} not found", target.name))
+}
+/// The crates the target depends on, directly or transitively, except the sysroot
+pub fn find_dependency_crates(
+    db: &RootDatabase,
+    vfs: &Vfs,
+    target: &TargetData,
+) -> Result<Vec<Crate>> {
     use ra_ap_base_db::CrateOrigin;
-    let crates = Crate::all(db);
+    let root = find_crate(db, vfs, target)?;
+    let mut reachable = fxhash::FxHashSet::default();
+    let mut stack = vec![root];
+    while let Some(krate) = stack.pop() {
+        for dep in krate.dependencies(db) {
+            if reachable.insert(dep.krate) {
+                stack.push(dep.krate);
+            }
+        }
+    }
     let root_path = target.root.as_path();
-    crates
+    // in the order of the crate graph, so that the output is deterministic
+    Ok(Crate::all(db)
         .into_iter()
         .filter(|krate| {
             let vfs_path = vfs.file_path(krate.root_file(db));
             let crate_root_path = vfs_path.as_path().unwrap();
-            crate_root_path != root_path
+            reachable.contains(krate)
+                && crate_root_path != root_path
                 && !matches!(
                     krate.origin(db),
                     CrateOrigin::Rustc { .. } | CrateOrigin::Lang(_)
                 )
         })
-        .collect()
+        .collect())
 }
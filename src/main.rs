@@ -6,10 +6,20 @@ use indicatif::{HumanDuration, MultiProgress, ProgressBar, ProgressStyle};
 use std::path::PathBuf;
 
 mod audit;
+mod baseline;
 mod bindgen;
+mod build_time;
+mod cache;
 mod candid;
 mod check;
+mod diff;
+mod graph;
 mod load_cargo;
+mod metadata;
+mod metrics;
+mod policy;
+mod sarif;
+mod system_api;
 mod utils;
 
 #[derive(Parser)]
@@ -20,14 +30,23 @@ struct App {
     command: Command,
 }
 
-#[derive(Parser)]
+#[derive(Clone, Parser)]
 struct Options {
     #[arg(short, long, default_value = ".")]
     /// The path for Cargo project root.
     pub manifest_path: PathBuf,
     #[arg(short, long)]
-    /// Package to process
-    pub package: Option<String>,
+    /// Package to process, can be repeated
+    pub package: Vec<String>,
+    #[arg(long, conflicts_with("package"))]
+    /// Process all cdylib packages in the workspace
+    pub workspace: bool,
+    #[arg(long, conflicts_with("bin"))]
+    /// Process the library target of the package
+    pub lib: bool,
+    #[arg(long, conflicts_with("workspace"))]
+    /// Process the named binary target of the package, e.g., a `#![no_main]` canister
+    pub bin: Option<String>,
     #[arg(long)]
     /// Do not activate the `default` feature.
     pub no_default_features: bool,
@@ -42,9 +61,16 @@ struct Options {
     pub verbose: bool,
     #[arg(hide = true, long)]
     pub expand_proc_macros: bool,
+    #[arg(long)]
+    /// Target triple for the analysis. Defaults to wasm32-unknown-unknown.
+    pub target: Option<String>,
+    #[arg(long, value_name = "KEY=VALUE")]
+    /// Enable a cfg option for all crates, e.g., `--cfg foo` or `--cfg target_os=linux`
+    pub cfg: Vec<String>,
 }
 
 #[derive(Parser)]
+#[allow(clippy::large_enum_variant)]
 enum Command {
     /// Check if dependent crates has any unsafe functions or exposes any canister endpoints.
     Audit {
@@ -53,9 +79,54 @@ enum Command {
         #[arg(short, long)]
         /// Trace unsafe functions from the main package. If false, scan external dependencies for import/export functions.
         trace_functions: bool,
-        #[arg(short, long, num_args = 1.., value_delimiter = ',', default_value = "ic0,ic-cdk")]
-        /// List of whitelisted crates.
+        #[arg(long, conflicts_with("trace_functions"))]
+        /// Audit build scripts and proc-macro crates for process, network and filesystem access, instead of canister code.
+        build_time: bool,
+        #[arg(long, requires("trace_functions"))]
+        /// Only trace from canister entry points (#[query], #[update], #[init], etc), and report findings per entry point.
+        entry_points: bool,
+        #[arg(long, requires("trace_functions"))]
+        /// Trace all impls of a trait method when the concrete impl cannot be determined (over-approximation).
+        all_impls: bool,
+        #[arg(short, long, num_args = 1.., value_delimiter = ',')]
+        /// List of whitelisted crates. Defaults to ic0,ic-cdk.
         whitelist: Vec<String>,
+        #[arg(long)]
+        /// Policy file with allow rules for reviewed findings. Defaults to canister-audit.toml in the project root, if present.
+        policy: Option<PathBuf>,
+        #[arg(long)]
+        /// Only report findings that are not in this baseline file.
+        baseline: Option<PathBuf>,
+        #[arg(long, conflicts_with("baseline"))]
+        /// Record the current findings to a baseline file.
+        write_baseline: Option<PathBuf>,
+        #[arg(long, requires("write_baseline"))]
+        /// Match baseline findings regardless of the crate version.
+        baseline_ignore_version: bool,
+        #[arg(long, value_name = "REV_OR_LOCKFILE")]
+        /// Report findings added and removed since a git revision or a Cargo.lock file.
+        diff_against: Option<String>,
+        #[arg(long, default_value = "release")]
+        /// Cargo profile whose `overflow-checks` setting decides if integer arithmetic may panic.
+        profile: String,
+        #[arg(long, requires("trace_functions"))]
+        /// Write the traced call graph to a file, in JSON if the extension is .json, or in DOT format otherwise.
+        graph: Option<PathBuf>,
+        #[arg(long, conflicts_with_all(["build_time", "diff_against", "graph"]))]
+        /// Audit for both the analysis target and the host target, and only report findings that exist under one of them. Policies apply to all findings.
+        all_targets: bool,
+        #[arg(long)]
+        /// Scan all dependencies, instead of reusing results cached under target/canister-cache.
+        no_cache: bool,
+        #[arg(long, value_enum, default_value = "human")]
+        /// Output format of the audit findings.
+        format: audit::Format,
+        #[arg(long, num_args = 1.., value_delimiter = ',')]
+        /// Finding kinds that fail the audit with a non-zero exit code.
+        deny: Vec<audit::Kind>,
+        #[arg(long, num_args = 1.., value_delimiter = ',')]
+        /// Finding kinds that are counted in the summary, but do not fail the audit.
+        warn: Vec<audit::Kind>,
     },
     #[command(hide = true)]
     /// Export Candid interface from Rust project
@@ -66,8 +137,11 @@ enum Command {
     /// Generate Rust bindings from Candid interface
     Bindgen {
         #[arg(short, long, default_value = ".")]
-        /// The path for canister.toml file
-        canister_path: PathBuf,
+        /// The path for Cargo project root, whose Cargo.toml provides the default canister path.
+        manifest_path: PathBuf,
+        #[arg(short, long)]
+        /// The path for canister.toml file. Defaults to `canister-path` in `[package.metadata.canister]`, or the current directory.
+        canister_path: Option<PathBuf>,
         #[arg(short, long)]
         /// Write the bindgen to disk
         write: bool,
@@ -86,10 +160,362 @@ impl Command {
     }
 }
 
-fn main() -> Result<()> {
+/// Settings shared by every audit run in the same invocation.
+struct AuditConfig<'a> {
+    trace_functions: bool,
+    build_time: bool,
+    entry_points: bool,
+    all_impls: bool,
+    whitelist: &'a [String],
+    allow_rules: &'a [policy::AllowRule],
+    baseline: Option<&'a baseline::Baseline>,
+    print_findings: bool,
+    graph: bool,
+    cache: bool,
+    profile: &'a str,
+}
+#[derive(Default)]
+struct AuditResult {
+    /// Package name of the audited canister
+    canister: String,
+    findings: Vec<audit::Finding>,
+    graph: Option<graph::CallGraph>,
+    /// Build scripts and proc-macro crates in `--build-time` mode
+    targets: Vec<build_time::Target>,
+    metrics: metrics::Metrics,
+    size: usize,
+    /// Target triple of the analysis, after applying the package metadata
+    target: String,
+    /// Functions audited per target with `--all-targets`
+    sizes: Vec<(String, usize)>,
+    baselined: Vec<audit::Finding>,
+}
+impl AuditResult {
+    fn new(canister: String) -> Self {
+        AuditResult {
+            canister,
+            ..Default::default()
+        }
+    }
+    /// Makes the finding locations relative to the current directory for output
+    fn relativize(&mut self) {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let findings = self.findings.iter_mut().chain(&mut self.baselined);
+        for location in findings.filter_map(|f| f.location.as_mut()) {
+            location.relativize(&cwd);
+        }
+    }
+    fn collect(&mut self, output: audit::Output) {
+        self.size += output.visited;
+        self.baselined.extend(output.baselined);
+        self.findings.extend(output.findings);
+        self.metrics.merge(output.metrics);
+        if output.graph.is_some() {
+            self.graph = output.graph;
+        }
+    }
+}
+/// Audits every selected canister with a single loaded database. Build scripts and dependencies
+/// are scanned once, and shared by all canisters.
+fn run_audit(
+    options: &Options,
+    bars: &MultiProgress,
+    is_verbose: bool,
+    config: &AuditConfig,
+) -> Result<Vec<AuditResult>> {
+    use audit::Mode;
     use load_cargo::{
-        find_crate, find_non_root_crates, find_whitelisted_crates, load_cargo_project,
+        find_crate, find_dependency_crates, find_whitelisted_crates, load_cargo_metadata,
+        load_cargo_project, overflow_checks,
+    };
+    let canisters = |ws: &ra_ap_project_model::CargoWorkspace,
+                     targets: &[ra_ap_project_model::TargetData]| {
+        targets
+            .iter()
+            .map(|target| AuditResult::new(ws[target.package].name.clone()))
+            .collect::<Vec<_>>()
     };
+    if config.build_time {
+        // The code under audit must not run before it is reviewed, so only cargo metadata is loaded
+        let (ws, targets, metadata) = load_cargo_metadata(options, bars)?;
+        let whitelist_names = metadata.whitelist(config.whitelist);
+        let mut results = canisters(&ws, &targets);
+        let mut scanner = build_time::Scanner::new(bars, is_verbose, &whitelist_names);
+        scanner.print_findings = config.print_findings;
+        scanner.allow_rules = config.allow_rules;
+        scanner.baseline = config.baseline;
+        scanner.scan(&ws);
+        for res in &mut results {
+            res.findings.clone_from(&scanner.findings);
+            res.targets.clone_from(&scanner.targets);
+            res.baselined.clone_from(&scanner.baselined);
+        }
+        return Ok(results);
+    }
+    let (ws, db, vfs, targets, metadata) = load_cargo_project(options, bars)?;
+    let options = &metadata.apply(options);
+    let whitelist_names = metadata.whitelist(config.whitelist);
+    let whitelist = find_whitelisted_crates(&ws, &db, &vfs, &whitelist_names)?;
+    let mut results = canisters(&ws, &targets);
+    let target = options
+        .target
+        .clone()
+        .unwrap_or_else(|| "wasm32-unknown-unknown".to_string());
+    for res in &mut results {
+        res.target.clone_from(&target);
+    }
+    if config.trace_functions {
+        for (target, res) in targets.iter().zip(&mut results) {
+            if targets.len() > 1 && config.print_findings {
+                let msg = format!(
+                    "{:>12} {}",
+                    Style::new().cyan().bold().apply_to("Canister"),
+                    res.canister
+                );
+                if is_verbose {
+                    log::info!("{msg}");
+                } else {
+                    bars.println(msg)?;
+                }
+            }
+            let krate = find_crate(&db, &vfs, target)?;
+            let mut builder = audit::Builder::new(
+                bars,
+                is_verbose,
+                &db,
+                &vfs,
+                krate,
+                whitelist.clone(),
+                if config.entry_points {
+                    Mode::EntryPoints
+                } else {
+                    Mode::TraceFunctions
+                },
+            );
+            builder.print_findings = config.print_findings;
+            builder.allow_rules = config.allow_rules;
+            builder.baseline = config.baseline;
+            builder.all_impls = config.all_impls;
+            builder.overflow_checks = overflow_checks(&ws, config.profile);
+            if config.graph {
+                builder.graph = Some(graph::CallGraph::default());
+            }
+            builder.build();
+            res.collect(builder.finish());
+        }
+    } else {
+        use ra_ap_base_db::salsa::ParallelDatabase;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        // Each canister scans its dependencies, so the union is scanned only once
+        let per_target = targets
+            .iter()
+            .map(|target| find_dependency_crates(&db, &vfs, target))
+            .collect::<Result<Vec<_>>>()?;
+        let mut crates = Vec::new();
+        let mut crate_index = fxhash::FxHashMap::default();
+        for krate in per_target.iter().flatten() {
+            crate_index.entry(*krate).or_insert_with(|| {
+                crates.push(*krate);
+                crates.len() - 1
+            });
+        }
+        let bar = bars.add(ProgressBar::new(crates.len() as u64));
+        bar.set_style(
+            ProgressStyle::with_template("{prefix:>12.cyan.bold} [{bar:57.green}] {pos}/{len}")
+                .unwrap()
+                .progress_chars("=> "),
+        );
+        bar.set_prefix("Scanning");
+        let cache = if config.cache {
+            Some(cache::Cache::new(&ws, options, &whitelist_names)?)
+        } else {
+            None
+        };
+        let mut outputs = Vec::new();
+        let mut pending = Vec::new();
+        for (index, krate) in crates.into_iter().enumerate() {
+            match cache.as_ref().and_then(|cache| cache.get(&db, krate)) {
+                Some(output) => {
+                    bar.inc(1);
+                    outputs.push((index, output));
+                }
+                None => pending.push((index, krate)),
+            }
+        }
+        // Each worker scans the next unclaimed crate with its own database snapshot
+        let threads = std::thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(pending.len().max(1));
+        let snapshots: Vec<_> = (0..threads).map(|_| db.snapshot()).collect();
+        let next = AtomicUsize::new(0);
+        let (next, pending, vfs, whitelist, bar) = (&next, &pending, &vfs, &whitelist, &bar);
+        let scanned: Vec<_> = std::thread::scope(|s| {
+            let workers: Vec<_> = snapshots
+                .into_iter()
+                .map(|snapshot| {
+                    s.spawn(move || {
+                        let mut outputs = Vec::new();
+                        loop {
+                            let claimed = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(&(index, krate)) = pending.get(claimed) else {
+                                break;
+                            };
+                            let mut builder = audit::Builder::new(
+                                bars,
+                                is_verbose,
+                                &snapshot,
+                                vfs,
+                                krate,
+                                whitelist.clone(),
+                                Mode::ScanExports,
+                            );
+                            // printed after merging, so that the output is deterministic,
+                            // and policies are applied after caching
+                            builder.print_findings = false;
+                            builder.build();
+                            outputs.push((index, krate, builder.finish()));
+                            bar.inc(1);
+                        }
+                        outputs
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|w| w.join().expect("scan worker panicked"))
+                .collect()
+        });
+        bar.finish_and_clear();
+        for (index, krate, output) in scanned {
+            if let Some(cache) = &cache {
+                cache.put(&db, krate, &output)?;
+            }
+            outputs.push((index, output));
+        }
+        outputs.sort_by_key(|(index, _)| *index);
+        let mut applied = Vec::new();
+        for (_, mut output) in outputs {
+            output.apply(config.allow_rules, config.baseline);
+            if config.print_findings {
+                for finding in output.findings.iter().filter(|f| !f.is_suppressed()) {
+                    if is_verbose {
+                        log::error!("{}", finding.message());
+                    } else {
+                        bars.println(finding.message())?;
+                    }
+                }
+            }
+            applied.push(output);
+        }
+        for (crates, res) in per_target.iter().zip(&mut results) {
+            for krate in crates {
+                res.collect(applied[crate_index[krate]].clone());
+            }
+        }
+    }
+    Ok(results)
+}
+
+/// Audits for the analysis target and the host target. Findings that exist under only one of them,
+/// e.g., from `cfg(not(target_arch = "wasm32"))` branches, are labeled with their target.
+fn run_audit_all_targets(
+    options: &Options,
+    bars: &MultiProgress,
+    is_verbose: bool,
+    config: &AuditConfig,
+) -> Result<Vec<AuditResult>> {
+    let rustc = std::process::Command::new("rustc").arg("-vV").output()?;
+    let host = String::from_utf8_lossy(&rustc.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("Cannot determine the host target from rustc -vV"))?;
+    let results = run_audit(options, bars, is_verbose, config)?;
+    let target = results
+        .first()
+        .map(|res| res.target.clone())
+        .unwrap_or_default();
+    if target == host {
+        return Err(anyhow::anyhow!(
+            "--all-targets compares the analysis target with the host, but both are {host}"
+        ));
+    }
+    let mut runs = vec![(target, results)];
+    let mut options = options.clone();
+    options.target = Some(host.clone());
+    runs.push((host, run_audit(&options, bars, is_verbose, config)?));
+    let keys: Vec<std::collections::BTreeSet<_>> = runs
+        .iter()
+        .map(|(_, results)| {
+            results
+                .iter()
+                .flat_map(|res| {
+                    res.findings
+                        .iter()
+                        .map(|f| (res.canister.clone(), diff::key(f)))
+                })
+                .collect()
+        })
+        .collect();
+    let mut merged: Vec<AuditResult> = Vec::new();
+    for (i, (target, results)) in runs.into_iter().enumerate() {
+        for res in results {
+            let index = match merged.iter().position(|m| m.canister == res.canister) {
+                Some(index) => index,
+                None => {
+                    merged.push(AuditResult::new(res.canister.clone()));
+                    merged.len() - 1
+                }
+            };
+            let entry = &mut merged[index];
+            entry.sizes.push((target.clone(), res.size));
+            entry.baselined.extend(res.baselined);
+            entry.metrics.merge(res.metrics);
+            for mut finding in res.findings {
+                let key = (res.canister.clone(), diff::key(&finding));
+                let shared = keys
+                    .iter()
+                    .enumerate()
+                    .all(|(j, keys)| j == i || keys.contains(&key));
+                // shared findings are kept once, for the policy and the baseline
+                if !shared {
+                    finding.target = Some(target.clone());
+                    entry.findings.push(finding);
+                } else if i == 0 {
+                    entry.findings.push(finding);
+                }
+            }
+        }
+    }
+    Ok(merged)
+}
+/// The report itself for the canister in the current directory, or the per-canister sections when
+/// canisters are selected with `--workspace` or `--package`
+fn print_sections<T: serde::Serialize>(
+    mut sections: Vec<audit::Section<T>>,
+    selected: bool,
+) -> Result<()> {
+    let json = if !selected && sections.len() == 1 {
+        serde_json::to_string_pretty(&sections.remove(0).report)?
+    } else {
+        let sections = std::collections::BTreeMap::from([("canisters", sections)]);
+        serde_json::to_string_pretty(&sections)?
+    };
+    println!("{json}");
+    Ok(())
+}
+/// `graph.dot` is written to `graph-backend.dot` for the `backend` canister
+fn canister_path(path: &std::path::Path, canister: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}-{canister}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{canister}"),
+    };
+    path.with_file_name(name)
+}
+
+fn main() -> Result<()> {
+    use load_cargo::{find_crate, load_cargo_project};
     let cmd = App::parse().command;
     let is_verbose = cmd.is_verbose();
     let env = if is_verbose {
@@ -105,67 +531,264 @@ fn main() -> Result<()> {
         bars.set_draw_target(indicatif::ProgressDrawTarget::hidden());
     }
     let start = std::time::Instant::now();
+    let mut exit_code = 0;
     match cmd {
         Command::Audit {
             mut options,
             trace_functions,
+            build_time,
+            entry_points,
+            all_impls,
             whitelist,
+            policy: policy_path,
+            baseline,
+            write_baseline,
+            baseline_ignore_version,
+            diff_against,
+            profile,
+            graph: graph_path,
+            all_targets,
+            no_cache,
+            format,
+            deny,
+            warn,
         } => {
-            use audit::Mode;
-            options.expand_proc_macros = true;
-            let (ws, db, vfs, target) = load_cargo_project(&options, &bars)?;
-            let whitelist = find_whitelisted_crates(&ws, &db, &vfs, &whitelist)?;
-            let mut size = 0;
-            if trace_functions {
-                let krate = find_crate(&db, &vfs, &target)?;
-                let mut builder = audit::Builder::new(
-                    &bars,
-                    is_verbose,
-                    &db,
-                    krate,
-                    whitelist,
-                    Mode::TraceFunctions,
-                );
-                builder.build();
-                size += builder.visited.len();
+            use audit::Format;
+            // panics are only traced on paths from entry points
+            if !entry_points && deny.iter().chain(&warn).any(|k| *k == audit::Kind::Panic) {
+                return Err(anyhow::anyhow!(
+                    "--deny or --warn panic requires --trace-functions --entry-points"
+                ));
+            }
+            let policy = policy::Policy::new(deny, warn)?;
+            options.expand_proc_macros = !build_time;
+            let allow_rules = match policy_path {
+                Some(path) => policy::load_allow_rules(&path)?,
+                None => {
+                    let path = options.manifest_path.join("canister-audit.toml");
+                    if path.exists() {
+                        policy::load_allow_rules(&path)?
+                    } else {
+                        Vec::new()
+                    }
+                }
+            };
+            let baseline = baseline
+                .map(|path| baseline::Baseline::load(&path))
+                .transpose()?;
+            let config = AuditConfig {
+                trace_functions,
+                build_time,
+                entry_points,
+                all_impls,
+                whitelist: &whitelist,
+                allow_rules: &allow_rules,
+                baseline: baseline.as_ref(),
+                // findings are printed after comparing targets
+                print_findings: format == Format::Human && diff_against.is_none() && !all_targets,
+                graph: graph_path.is_some(),
+                cache: !no_cache,
+                profile: &profile,
+            };
+            let mut results = if all_targets {
+                run_audit_all_targets(&options, &bars, is_verbose, &config)?
             } else {
-                let crates = find_non_root_crates(&db, &vfs, &target);
-                let bar = bars.add(ProgressBar::new(crates.len() as u64));
-                bar.set_style(
-                    ProgressStyle::with_template(
-                        "{prefix:>12.cyan.bold} [{bar:57.green}] {pos}/{len}",
-                    )
-                    .unwrap()
-                    .progress_chars("=> "),
-                );
-                bar.set_prefix("Scanning");
-                for krate in crates {
-                    bar.inc(1);
-                    let mut builder = audit::Builder::new(
-                        &bars,
-                        is_verbose,
-                        &db,
-                        krate,
-                        whitelist.clone(),
-                        Mode::ScanExports,
-                    );
-                    builder.build();
-                    size += builder.visited.len();
+                run_audit(&options, &bars, is_verbose, &config)?
+            };
+            results.iter_mut().for_each(AuditResult::relativize);
+            let multiple = results.len() > 1;
+            let selected = options.workspace || !options.package.is_empty();
+            let mut diffs = Vec::new();
+            if let Some(rev) = diff_against {
+                let workspace = diff::OldWorkspace::new(&options.manifest_path, &rev)?;
+                let mut old_options = options.clone();
+                old_options
+                    .manifest_path
+                    .clone_from(&workspace.manifest_path);
+                let mut old = run_audit(&old_options, &bars, is_verbose, &config)?;
+                old.iter_mut().for_each(AuditResult::relativize);
+                drop(workspace);
+                for res in &mut results {
+                    let old = old
+                        .iter()
+                        .position(|old| old.canister == res.canister)
+                        .map(|i| old.swap_remove(i).findings)
+                        .unwrap_or_default();
+                    let diff = diff::Diff::new(old, std::mem::take(&mut res.findings));
+                    res.findings.clone_from(&diff.added);
+                    diffs.push(diff);
+                }
+            }
+            // Findings in shared dependencies and build scripts appear in every canister
+            let findings = diff::unique(results.iter().flat_map(|res| &res.findings));
+            let verdict = policy.check(&findings);
+            let status = if verdict.is_denied() {
+                exit_code = policy::DENY_EXIT_CODE;
+                Style::new().red().bold().apply_to("Failed")
+            } else {
+                Style::new().green().bold().apply_to("Finished")
+            };
+            if all_targets {
+                // only the findings that differ between the targets are reported
+                for res in &mut results {
+                    res.findings.retain(|f| f.target.is_some());
+                }
+            }
+            let mut summary = verdict.summary();
+            let baselined = diff::unique(results.iter().flat_map(|res| &res.baselined)).len();
+            if baselined > 0 {
+                summary.push(format!(
+                    "{:>12} {baselined} finding(s) already in baseline",
+                    Style::new().cyan().bold().apply_to("Skipped")
+                ));
+            }
+            if let Some(path) = graph_path {
+                for res in &results {
+                    let Some(graph) = &res.graph else {
+                        continue;
+                    };
+                    let path = if multiple {
+                        canister_path(&path, &res.canister)
+                    } else {
+                        path.clone()
+                    };
+                    let content = if path.extension().is_some_and(|ext| ext == "json") {
+                        serde_json::to_string_pretty(&graph.to_json(&res.findings))?
+                    } else {
+                        graph.to_dot(&res.findings)
+                    };
+                    std::fs::write(&path, content)?;
+                    summary.push(format!(
+                        "{:>12} call graph to {}",
+                        Style::new().cyan().bold().apply_to("Wrote"),
+                        path.display()
+                    ));
+                }
+            }
+            if let Some(path) = write_baseline {
+                let baseline = baseline::Baseline::new(&findings, baseline_ignore_version);
+                baseline.save(&path)?;
+                summary.push(format!(
+                    "{:>12} {} finding(s) to baseline {}",
+                    Style::new().cyan().bold().apply_to("Recorded"),
+                    baseline.len(),
+                    path.display()
+                ));
+            }
+            let audited = |res: &AuditResult| {
+                if build_time {
+                    format!("{} build scripts and proc-macro crates", res.targets.len())
+                } else if all_targets {
+                    let sizes: Vec<_> = res
+                        .sizes
+                        .iter()
+                        .map(|(target, size)| format!("{size} functions for {target}"))
+                        .collect();
+                    sizes.join(" and ")
+                } else {
+                    format!("{} functions", res.size)
+                }
+            };
+            if multiple {
+                for res in &results {
+                    let count = res.findings.iter().filter(|f| !f.is_suppressed()).count();
+                    summary.push(format!(
+                        "{:>12} {}: {}, {count} finding(s)",
+                        Style::new().cyan().bold().apply_to("Audited"),
+                        res.canister,
+                        audited(res)
+                    ));
+                }
+                summary.push(format!(
+                    "{:>12} auditing {} canisters in {}",
+                    status,
+                    results.len(),
+                    HumanDuration(start.elapsed())
+                ));
+            } else {
+                summary.push(format!(
+                    "{:>12} auditing {} in {}",
+                    status,
+                    audited(&results[0]),
+                    HumanDuration(start.elapsed())
+                ));
+            }
+            let finished = summary.join("\n");
+            match format {
+                Format::Human => {
+                    for (i, res) in results.iter().enumerate() {
+                        let diff = diffs.get(i);
+                        if multiple && (diff.is_some() || !res.metrics.is_empty()) {
+                            println!(
+                                "{:>12} {}",
+                                Style::new().cyan().bold().apply_to("Canister"),
+                                res.canister
+                            );
+                        }
+                        if all_targets {
+                            for finding in res.findings.iter().filter(|f| !f.is_suppressed()) {
+                                println!("{}", finding.message());
+                            }
+                        }
+                        if let Some(diff) = diff {
+                            diff.print();
+                        } else if !res.metrics.is_empty() {
+                            println!("{}", res.metrics.table());
+                        }
+                    }
+                    println!("{finished}");
+                }
+                Format::Json => {
+                    eprintln!("{finished}");
+                    if diffs.is_empty() {
+                        let reports = results.into_iter().map(|res| audit::Section {
+                            canister: res.canister,
+                            report: audit::Report {
+                                findings: res.findings,
+                                targets: res.targets,
+                                crates: res.metrics.rows(),
+                            },
+                        });
+                        print_sections(reports.collect(), selected)?;
+                    } else {
+                        let diffs =
+                            results
+                                .into_iter()
+                                .zip(diffs)
+                                .map(|(res, diff)| audit::Section {
+                                    canister: res.canister,
+                                    report: diff,
+                                });
+                        print_sections(diffs.collect(), selected)?;
+                    }
+                }
+                Format::Sarif => {
+                    eprintln!("{finished}");
+                    let runs: Vec<_> = results
+                        .iter()
+                        .map(|res| (res.canister.as_str(), res.findings.as_slice()))
+                        .collect();
+                    println!("{}", serde_json::to_string_pretty(&sarif::emit(&runs))?);
                 }
-                bar.finish_and_clear();
             }
-            println!(
-                "{:>12} auditing {} functions in {}",
-                Style::new().green().bold().apply_to("Finished"),
-                size,
-                HumanDuration(start.elapsed())
-            );
         }
         Command::Bindgen {
+            manifest_path,
             canister_path,
             write,
             line_diff,
         } => {
+            let manifest_path = if manifest_path.is_dir() {
+                manifest_path.join("Cargo.toml")
+            } else {
+                manifest_path
+            };
+            let canister_path = match canister_path {
+                Some(path) => path,
+                None => metadata::Metadata::from_manifest(&manifest_path)?
+                    .canister_path
+                    .unwrap_or_else(|| PathBuf::from(".")),
+            };
             let opt = bindgen::Opt {
                 is_write: write,
                 line_diff,
@@ -174,13 +797,21 @@ fn main() -> Result<()> {
         }
         Command::Candid { mut options } => {
             options.expand_proc_macros = false;
-            let (_, db, vfs, target) = load_cargo_project(&options, &bars)?;
-            let krate = find_crate(&db, &vfs, &target)?;
-            let mut builder = candid::Builder::new(&db, krate);
-            builder.build();
-            println!("{}", builder.emit_methods());
+            let (ws, db, vfs, targets, _) = load_cargo_project(&options, &bars)?;
+            for target in &targets {
+                if targets.len() > 1 {
+                    println!("// {}", ws[target.package].name);
+                }
+                let krate = find_crate(&db, &vfs, target)?;
+                let mut builder = candid::Builder::new(&db, krate);
+                builder.build();
+                println!("{}", builder.emit_methods());
+            }
         }
     }
     bars.clear()?;
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
     Ok(())
 }
@@ -0,0 +1,114 @@
+use crate::Options;
+use anyhow::Result;
+use ra_ap_project_model::{CargoWorkspace, TargetData};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use toml::Table;
+
+/// Defaults from `[package.metadata.canister]` or `[workspace.metadata.canister]` in Cargo.toml.
+/// Flags on the command line take precedence.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Metadata {
+    pub whitelist: Option<Vec<String>>,
+    pub features: Option<Vec<String>>,
+    /// Target triple for the analysis
+    pub target: Option<String>,
+    /// Directory of canister.toml for bindgen, relative to the Cargo.toml that declares it
+    pub canister_path: Option<PathBuf>,
+}
+const DEFAULT_WHITELIST: [&str; 2] = ["ic0", "ic-cdk"];
+const KEYS: [&str; 4] = ["whitelist", "features", "target", "canister-path"];
+impl Metadata {
+    /// Workspace defaults, overridden by the package defaults when a single package is selected.
+    /// Selected packages share one loaded project, so with several of them, only the workspace
+    /// defaults apply.
+    pub fn load(cargo: &CargoWorkspace, targets: &[TargetData]) -> Result<Self> {
+        let root: &Path = cargo.workspace_root().as_ref();
+        let manifest = root.join("Cargo.toml");
+        let workspace = Self::from_section(&manifest, "workspace")?;
+        if let [target] = targets {
+            let manifest: &Path = (*cargo[target.package].manifest).as_ref();
+            return Ok(Self::from_section(manifest, "package")?.or(workspace));
+        }
+        for target in targets {
+            let manifest: &Path = (*cargo[target.package].manifest).as_ref();
+            if Self::from_section(manifest, "package")? != Metadata::default() {
+                log::warn!(
+                    "Ignoring [package.metadata.canister] of {}, as several packages are selected",
+                    cargo[target.package].name
+                );
+            }
+        }
+        Ok(workspace)
+    }
+    /// Package and workspace defaults declared in the same manifest, e.g., for bindgen
+    pub fn from_manifest(path: &Path) -> Result<Self> {
+        let workspace = Self::from_section(path, "workspace")?;
+        let package = Self::from_section(path, "package")?;
+        Ok(package.or(workspace))
+    }
+    fn from_section(path: &Path, section: &str) -> Result<Self> {
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return Ok(Metadata::default());
+        };
+        let manifest: Table = toml::from_str(&content)?;
+        let Some(table) = manifest
+            .get(section)
+            .and_then(|s| s.get("metadata"))
+            .and_then(|m| m.get("canister"))
+        else {
+            return Ok(Metadata::default());
+        };
+        let unknown = table
+            .as_table()
+            .into_iter()
+            .flat_map(|table| table.keys())
+            .filter(|key| !KEYS.contains(&key.as_str()));
+        for key in unknown {
+            log::warn!(
+                "Unknown key `{key}` in [{section}.metadata.canister] of {}",
+                path.display()
+            );
+        }
+        let mut metadata: Metadata = table.clone().try_into().map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid [{section}.metadata.canister] in {}: {e}",
+                path.display()
+            )
+        })?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        metadata.canister_path = metadata.canister_path.map(|p| dir.join(p));
+        Ok(metadata)
+    }
+    fn or(self, other: Metadata) -> Metadata {
+        Metadata {
+            whitelist: self.whitelist.or(other.whitelist),
+            features: self.features.or(other.features),
+            target: self.target.or(other.target),
+            canister_path: self.canister_path.or(other.canister_path),
+        }
+    }
+    /// Options with features and target filled in, unless given on the command line
+    pub fn apply(&self, options: &Options) -> Options {
+        let mut options = options.clone();
+        if options.features.is_empty() && !options.all_features {
+            if let Some(features) = &self.features {
+                options.features.clone_from(features);
+            }
+        }
+        if options.target.is_none() {
+            options.target.clone_from(&self.target);
+        }
+        options
+    }
+    pub fn whitelist(&self, cli: &[String]) -> Vec<String> {
+        if !cli.is_empty() {
+            cli.to_vec()
+        } else if let Some(whitelist) = &self.whitelist {
+            whitelist.clone()
+        } else {
+            DEFAULT_WHITELIST.map(String::from).to_vec()
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::audit::{Finding, Kind};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Footprint {
+    whitelisted: bool,
+    functions: BTreeSet<String>,
+    unsafe_functions: BTreeSet<String>,
+    imports: BTreeSet<String>,
+    exports: BTreeSet<String>,
+}
+/// Per-crate unsafe and import footprint of the audited functions
+type Entry = ((String, Option<String>), Footprint);
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<Entry>", into = "Vec<Entry>")]
+pub struct Metrics {
+    crates: BTreeMap<(String, Option<String>), Footprint>,
+}
+impl From<Vec<Entry>> for Metrics {
+    fn from(entries: Vec<Entry>) -> Self {
+        Metrics {
+            crates: entries.into_iter().collect(),
+        }
+    }
+}
+impl From<Metrics> for Vec<Entry> {
+    fn from(metrics: Metrics) -> Self {
+        metrics.crates.into_iter().collect()
+    }
+}
+#[derive(Serialize)]
+pub struct CrateMetrics {
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub version: Option<String>,
+    pub functions: usize,
+    pub unsafe_functions: usize,
+    pub imports: usize,
+    pub exports: usize,
+    pub whitelisted: bool,
+}
+impl Metrics {
+    pub fn add_function(
+        &mut self,
+        function: String,
+        krate: String,
+        version: Option<String>,
+        whitelisted: bool,
+    ) {
+        let entry = self.crates.entry((krate, version)).or_default();
+        entry.whitelisted |= whitelisted;
+        entry.functions.insert(function);
+    }
+    /// Whitelisted crates are listed, even if they are not scanned
+    pub fn add_whitelisted(&mut self, krate: String, version: Option<String>) {
+        self.crates.entry((krate, version)).or_default().whitelisted = true;
+    }
+    pub fn add_unsafe(&mut self, function: String, krate: String, version: Option<String>) {
+        let entry = self.crates.entry((krate, version)).or_default();
+        entry.unsafe_functions.insert(function);
+    }
+    /// Findings are counted before allow rules and baselines are applied
+    pub fn add_finding(&mut self, finding: &Finding) {
+        if !matches!(finding.kind, Kind::Unsafe | Kind::Import | Kind::Export) {
+            return;
+        }
+        let key = (finding.krate.clone(), finding.version.clone());
+        let entry = self.crates.entry(key).or_default();
+        entry.whitelisted |= finding.whitelisted;
+        let set = match finding.kind {
+            Kind::Unsafe => &mut entry.unsafe_functions,
+            Kind::Import => &mut entry.imports,
+            _ => &mut entry.exports,
+        };
+        set.insert(finding.function.clone());
+    }
+    /// Functions shared between runs are counted once
+    pub fn merge(&mut self, other: Metrics) {
+        for (key, footprint) in other.crates {
+            let entry = self.crates.entry(key).or_default();
+            entry.whitelisted |= footprint.whitelisted;
+            entry.functions.extend(footprint.functions);
+            entry.unsafe_functions.extend(footprint.unsafe_functions);
+            entry.imports.extend(footprint.imports);
+            entry.exports.extend(footprint.exports);
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.crates.is_empty()
+    }
+    pub fn rows(&self) -> Vec<CrateMetrics> {
+        self.crates
+            .iter()
+            .map(|((krate, version), f)| CrateMetrics {
+                krate: krate.clone(),
+                version: version.clone(),
+                functions: f.functions.len(),
+                unsafe_functions: f.unsafe_functions.len(),
+                imports: f.imports.len(),
+                exports: f.exports.len(),
+                whitelisted: f.whitelisted,
+            })
+            .collect()
+    }
+    /// Aligned table for terminal output
+    pub fn table(&self) -> String {
+        let header = [
+            "Crate",
+            "Version",
+            "Functions",
+            "Unsafe",
+            "Imports",
+            "Exports",
+            "Whitelisted",
+        ];
+        let rows: Vec<[String; 7]> = self
+            .rows()
+            .into_iter()
+            .map(|r| {
+                [
+                    r.krate,
+                    r.version.unwrap_or_default(),
+                    r.functions.to_string(),
+                    r.unsafe_functions.to_string(),
+                    r.imports.to_string(),
+                    r.exports.to_string(),
+                    if r.whitelisted { "yes" } else { "no" }.to_string(),
+                ]
+            })
+            .collect();
+        let mut widths = header.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        let format_row = |row: [&str; 7]| {
+            let cells: Vec<_> = row
+                .iter()
+                .zip(widths)
+                .enumerate()
+                .map(|(i, (cell, width))| {
+                    // names are left aligned, counts are right aligned
+                    if i < 2 || i == 6 {
+                        format!("{cell:<width$}")
+                    } else {
+                        format!("{cell:>width$}")
+                    }
+                })
+                .collect();
+            cells.join("  ").trim_end().to_string()
+        };
+        let mut lines = vec![format_row(header)];
+        for row in &rows {
+            lines.push(format_row(row.each_ref().map(String::as_str)));
+        }
+        lines.join("\n")
+    }
+}
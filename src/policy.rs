@@ -0,0 +1,149 @@
+use crate::audit::{Finding, Kind};
+use anyhow::Result;
+use clap::ValueEnum;
+use console::Style;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Exit code when the audit finds denied findings.
+pub const DENY_EXIT_CODE: i32 = 3;
+
+pub struct Policy {
+    deny: Vec<Kind>,
+    warn: Vec<Kind>,
+}
+pub struct Verdict {
+    /// `(kind, count, is_deny)`: number of unsuppressed findings for each warned or denied kind
+    pub counts: Vec<(Kind, usize, bool)>,
+}
+impl Policy {
+    pub fn new(mut deny: Vec<Kind>, mut warn: Vec<Kind>) -> Result<Self> {
+        if let Some(kind) = deny.iter().find(|k| warn.contains(k)) {
+            let kind = kind.to_possible_value().unwrap();
+            return Err(anyhow::anyhow!(
+                "{} cannot be in both --deny and --warn",
+                kind.get_name()
+            ));
+        }
+        // `--deny unsafe,unsafe` counts the kind once
+        deny.sort();
+        deny.dedup();
+        warn.sort();
+        warn.dedup();
+        Ok(Self { deny, warn })
+    }
+    pub fn check(&self, findings: &[Finding]) -> Verdict {
+        let count = |kind: &Kind| {
+            findings
+                .iter()
+                .filter(|f| f.kind == *kind && !f.is_suppressed())
+                .count()
+        };
+        let counts = self
+            .deny
+            .iter()
+            .map(|k| (*k, count(k), true))
+            .chain(self.warn.iter().map(|k| (*k, count(k), false)))
+            .collect();
+        Verdict { counts }
+    }
+}
+impl Verdict {
+    pub fn is_denied(&self) -> bool {
+        self.counts
+            .iter()
+            .any(|(_, count, is_deny)| *is_deny && *count > 0)
+    }
+    pub fn summary(&self) -> Vec<String> {
+        self.counts
+            .iter()
+            .filter(|(_, count, _)| *count > 0)
+            .map(|(kind, count, is_deny)| {
+                let (label, style) = if *is_deny {
+                    ("Denied", Style::new().red().bold())
+                } else {
+                    ("Warning", Style::new().yellow().bold())
+                };
+                let kind = kind.to_possible_value().unwrap();
+                format!(
+                    "{:>12} {count} {} finding(s)",
+                    style.apply_to(label),
+                    kind.get_name()
+                )
+            })
+            .collect()
+    }
+}
+
+/// Policy file to narrowly allow reviewed findings, e.g.,
+/// ```toml
+/// [[allow]]
+/// crate = "my_library@0.1.0"
+/// kind = "import"
+/// function = "my_library::stable64_size"
+/// justification = "Reviewed: read-only System API call"
+/// ```
+#[derive(Deserialize)]
+struct PolicyFile {
+    #[serde(default)]
+    allow: Vec<AllowRule>,
+}
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AllowRule {
+    /// `name` or `name@version`
+    #[serde(rename = "crate")]
+    krate: Option<String>,
+    kind: Option<Kind>,
+    /// Full path of the function. A trailing `*` matches any suffix.
+    function: Option<String>,
+    pub justification: String,
+}
+impl AllowRule {
+    pub fn matches(&self, finding: &Finding) -> bool {
+        if let Some(krate) = &self.krate {
+            let (name, version) = match krate.split_once('@') {
+                Some((name, version)) => (name, Some(version)),
+                None => (krate.as_str(), None),
+            };
+            if name.replace('-', "_") != finding.krate {
+                return false;
+            }
+            if version.is_some() && version != finding.version.as_deref() {
+                return false;
+            }
+        }
+        if self.kind.is_some_and(|kind| kind != finding.kind) {
+            return false;
+        }
+        match self.function.as_deref() {
+            None => true,
+            Some(func) => match func.strip_suffix('*') {
+                Some(prefix) => finding.function.starts_with(prefix),
+                None => finding.function == func,
+            },
+        }
+    }
+}
+pub fn load_allow_rules(path: &Path) -> Result<Vec<AllowRule>> {
+    let content = std::fs::read_to_string(path)?;
+    let file: PolicyFile = toml::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("Cannot parse {}: {e}", path.display()))?;
+    for (i, rule) in file.allow.iter().enumerate() {
+        if rule.krate.is_none() && rule.function.is_none() {
+            return Err(anyhow::anyhow!(
+                "allow rule #{} in {} needs a crate or function",
+                i + 1,
+                path.display()
+            ));
+        }
+        if rule.justification.trim().is_empty() {
+            return Err(anyhow::anyhow!(
+                "allow rule #{} in {} has an empty justification",
+                i + 1,
+                path.display()
+            ));
+        }
+    }
+    Ok(file.allow)
+}
@@ -0,0 +1,131 @@
+use crate::audit::{Finding, Kind};
+use serde_json::{json, Value};
+
+const KINDS: [Kind; 11] = [
+    Kind::Import,
+    Kind::InvalidImport,
+    Kind::Export,
+    Kind::CustomSection,
+    Kind::GlobalState,
+    Kind::BorrowAcrossAwait,
+    Kind::Panic,
+    Kind::UnsupportedApi,
+    Kind::CanisterCall,
+    Kind::BuildTime,
+    Kind::Unsafe,
+];
+
+fn rule_id(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Import => "external-import",
+        Kind::InvalidImport => "invalid-import",
+        Kind::Export => "export-name",
+        Kind::CustomSection => "custom-section",
+        Kind::GlobalState => "global-state",
+        Kind::BorrowAcrossAwait => "borrow-across-await",
+        Kind::Panic => "panic",
+        Kind::UnsupportedApi => "unsupported-api",
+        Kind::CanisterCall => "canister-call",
+        Kind::BuildTime => "build-time",
+        Kind::Unsafe => "unsafe-block",
+    }
+}
+fn level(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Import | Kind::InvalidImport | Kind::BorrowAcrossAwait | Kind::UnsupportedApi => {
+            "error"
+        }
+        Kind::Export
+        | Kind::CustomSection
+        | Kind::GlobalState
+        | Kind::Panic
+        | Kind::CanisterCall
+        | Kind::BuildTime
+        | Kind::Unsafe => "warning",
+    }
+}
+fn rule(kind: Kind) -> Value {
+    let desc = match kind {
+        Kind::Import => "Function is imported from the host via an extern block",
+        Kind::InvalidImport => {
+            "Import is not part of the IC System API, or has the wrong signature"
+        }
+        Kind::Export => "Function is exported via #[export_name] or #[no_mangle]",
+        Kind::CustomSection => "Static is placed in a custom wasm section via #[link_section]",
+        Kind::GlobalState => "Global mutable state is wiped on upgrade unless persisted explicitly",
+        Kind::BorrowAcrossAwait => "RefCell borrow or lock guard is held across an await point",
+        Kind::Panic => "Code reachable from the entry point may panic and trap the canister",
+        Kind::BuildTime => {
+            "Build script or proc-macro spawns processes, accesses the network, or writes outside OUT_DIR"
+        }
+        Kind::CanisterCall => "Entry point makes an inter-canister call or transfers cycles",
+        Kind::UnsupportedApi => {
+            "API is non-deterministic or not supported on wasm32-unknown-unknown"
+        }
+        Kind::Unsafe => "Function contains unsafe blocks",
+    };
+    json!({
+        "id": rule_id(kind),
+        "shortDescription": { "text": desc },
+        "defaultConfiguration": { "level": level(kind) },
+    })
+}
+fn result(finding: &Finding) -> Value {
+    let mut res = json!({
+        "ruleId": rule_id(finding.kind),
+        "level": level(finding.kind),
+        "message": { "text": finding.text() },
+        "properties": {
+            "crate": finding.krate,
+            "version": finding.version,
+            "path": finding.path,
+            "target": finding.target,
+        },
+    });
+    if let Some(loc) = &finding.location {
+        res["locations"] = json!([{
+            "physicalLocation": {
+                "artifactLocation": { "uri": loc.file.replace('\\', "/") },
+                "region": {
+                    "startLine": loc.line,
+                    "startColumn": loc.column,
+                    "endLine": loc.end_line,
+                    "endColumn": loc.end_column,
+                },
+            },
+        }]);
+    }
+    if let Some(justification) = &finding.justification {
+        res["suppressions"] = json!([{
+            "kind": "external",
+            "justification": justification,
+        }]);
+    } else if finding.whitelisted {
+        res["suppressions"] = json!([{
+            "kind": "external",
+            "justification": format!("crate {} is whitelisted", finding.krate),
+        }]);
+    }
+    res
+}
+fn run(canister: &str, findings: &[Finding]) -> Value {
+    json!({
+        "tool": {
+            "driver": {
+                "name": env!("CARGO_PKG_NAME"),
+                "version": env!("CARGO_PKG_VERSION"),
+                "rules": KINDS.iter().map(|k| rule(*k)).collect::<Vec<_>>(),
+            },
+        },
+        "automationDetails": { "id": format!("{canister}/") },
+        "results": findings.iter().map(result).collect::<Vec<_>>(),
+    })
+}
+/// One run per canister
+pub fn emit(runs: &[(&str, &[Finding])]) -> Value {
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": runs.iter().map(|(canister, findings)| run(canister, findings)).collect::<Vec<_>>(),
+    })
+}
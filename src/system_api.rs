@@ -0,0 +1,267 @@
+//! The IC System API imported from the `ic0` wasm module, see
+//! <https://internetcomputer.org/docs/current/references/ic-interface-spec#system-api-imports>.
+//! `I` is `i32`, as we only support wasm32 canisters.
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+impl fmt::Display for ValType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+            ValType::F32 => "f32",
+            ValType::F64 => "f64",
+        };
+        write!(f, "{s}")
+    }
+}
+use ValType::{I32, I64};
+
+pub struct SystemApi {
+    pub name: &'static str,
+    pub params: &'static [ValType],
+    pub results: &'static [ValType],
+    /// Contexts in which the function can be called, using the abbreviations from the spec
+    pub contexts: &'static str,
+}
+const fn api(
+    name: &'static str,
+    params: &'static [ValType],
+    results: &'static [ValType],
+    contexts: &'static str,
+) -> SystemApi {
+    SystemApi {
+        name,
+        params,
+        results,
+        contexts,
+    }
+}
+const REPLY: &str = "U RQ NRQ CQ Ry Rt CRy CRt";
+const CALL: &str = "U CQ Ry Rt CRy CRt T";
+#[rustfmt::skip]
+const SYSTEM_API: &[SystemApi] = &[
+    api("msg_arg_data_size", &[], &[I32], "I U RQ NRQ CQ Ry CRy F"),
+    api("msg_arg_data_copy", &[I32, I32, I32], &[], "I U RQ NRQ CQ Ry CRy F"),
+    api("msg_caller_size", &[], &[I32], "*"),
+    api("msg_caller_copy", &[I32, I32, I32], &[], "*"),
+    api("msg_reject_code", &[], &[I32], "Ry Rt CRy CRt"),
+    api("msg_reject_msg_size", &[], &[I32], "Rt CRt"),
+    api("msg_reject_msg_copy", &[I32, I32, I32], &[], "Rt CRt"),
+    api("msg_deadline", &[], &[I64], REPLY),
+    api("msg_reply_data_append", &[I32, I32], &[], REPLY),
+    api("msg_reply", &[], &[], REPLY),
+    api("msg_reject", &[I32, I32], &[], REPLY),
+    api("msg_cycles_available", &[], &[I64], "U RQ Rt Ry"),
+    api("msg_cycles_available128", &[I32], &[], "U RQ Rt Ry"),
+    api("msg_cycles_refunded", &[], &[I64], "Rt Ry"),
+    api("msg_cycles_refunded128", &[I32], &[], "Rt Ry"),
+    api("msg_cycles_accept", &[I64], &[I64], "U RQ Rt Ry"),
+    api("msg_cycles_accept128", &[I64, I64, I32], &[], "U RQ Rt Ry"),
+    api("cycles_burn128", &[I64, I64, I32], &[], "I G U Ry Rt C T"),
+    api("canister_self_size", &[], &[I32], "*"),
+    api("canister_self_copy", &[I32, I32, I32], &[], "*"),
+    api("canister_cycle_balance", &[], &[I64], "*"),
+    api("canister_cycle_balance128", &[I32], &[], "*"),
+    api("canister_liquid_cycle_balance128", &[I32], &[], "*"),
+    api("canister_status", &[], &[I32], "*"),
+    api("canister_version", &[], &[I64], "*"),
+    api("subnet_self_size", &[], &[I32], "*"),
+    api("subnet_self_copy", &[I32, I32, I32], &[], "*"),
+    api("msg_method_name_size", &[], &[I32], "F"),
+    api("msg_method_name_copy", &[I32, I32, I32], &[], "F"),
+    api("accept_message", &[], &[], "F"),
+    api("call_new", &[I32, I32, I32, I32, I32, I32, I32, I32], &[], CALL),
+    api("call_on_cleanup", &[I32, I32], &[], CALL),
+    api("call_data_append", &[I32, I32], &[], CALL),
+    api("call_with_best_effort_response", &[I32], &[], CALL),
+    api("call_cycles_add", &[I64], &[], "U Ry Rt T"),
+    api("call_cycles_add128", &[I64, I64], &[], "U Ry Rt T"),
+    api("call_perform", &[], &[I32], CALL),
+    api("stable_size", &[], &[I32], "* s"),
+    api("stable_grow", &[I32], &[I32], "* s"),
+    api("stable_write", &[I32, I32, I32], &[], "* s"),
+    api("stable_read", &[I32, I32, I32], &[], "* s"),
+    api("stable64_size", &[], &[I64], "* s"),
+    api("stable64_grow", &[I64], &[I64], "* s"),
+    api("stable64_write", &[I64, I64, I64], &[], "* s"),
+    api("stable64_read", &[I64, I64, I64], &[], "* s"),
+    api("root_key_size", &[], &[I32], "*"),
+    api("root_key_copy", &[I32, I32, I32], &[], "*"),
+    api("certified_data_set", &[I32, I32], &[], "I G U Ry Rt T"),
+    api("data_certificate_present", &[], &[I32], "*"),
+    api("data_certificate_size", &[], &[I32], "NRQ CQ"),
+    api("data_certificate_copy", &[I32, I32, I32], &[], "NRQ CQ"),
+    api("time", &[], &[I64], "*"),
+    api("global_timer_set", &[I64], &[I64], "I G U Ry Rt C T"),
+    api("performance_counter", &[I32], &[I64], "* s"),
+    api("is_controller", &[I32, I32], &[I32], "* s"),
+    api("in_replicated_execution", &[], &[I32], "* s"),
+    api("cost_call", &[I64, I64, I32], &[], "*"),
+    api("cost_create_canister", &[I32], &[], "*"),
+    api("cost_http_request", &[I64, I64, I32], &[], "*"),
+    api("cost_sign_with_ecdsa", &[I32, I32, I32, I32], &[I32], "*"),
+    api("cost_sign_with_schnorr", &[I32, I32, I32, I32], &[I32], "*"),
+    api("cost_vetkd_derive_key", &[I32, I32, I32, I32], &[I32], "*"),
+    api("mint_cycles", &[I64], &[I64], "U Rt Ry"),
+    api("mint_cycles128", &[I64, I64, I32], &[], "U Rt Ry"),
+    api("debug_print", &[I32, I32], &[], "* s"),
+    api("trap", &[I32, I32], &[], "* s"),
+];
+
+/// Wasm type of a Rust FFI type on wasm32
+fn val_type(ty: &str) -> Option<ValType> {
+    match ty {
+        "i8" | "u8" | "i16" | "u16" | "i32" | "u32" | "isize" | "usize" | "bool" | "char" => {
+            Some(ValType::I32)
+        }
+        "i64" | "u64" => Some(ValType::I64),
+        "f32" => Some(ValType::F32),
+        "f64" => Some(ValType::F64),
+        _ if ty.starts_with(['*', '&'])
+            || ty.starts_with("fn(")
+            || ty.starts_with("unsafe ")
+            || ty.starts_with("extern ") =>
+        {
+            Some(ValType::I32)
+        }
+        _ => None,
+    }
+}
+fn pp_types(types: &[ValType]) -> String {
+    types
+        .iter()
+        .map(|t| t.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+/// Check an import against the System API. `params` and `result` are the Rust types in the extern
+/// declaration, with `result` being `None` for `()` or `!`.
+pub fn check_import(
+    module: &str,
+    name: &str,
+    params: &[String],
+    result: Option<&str>,
+) -> Result<&'static SystemApi, String> {
+    if module != "ic0" {
+        return Err(format!("imports from unknown wasm module \"{module}\""));
+    }
+    let api = SYSTEM_API
+        .iter()
+        .find(|api| api.name == name)
+        .ok_or_else(|| format!("ic0.{name} is not part of the System API"))?;
+    let to_wasm =
+        |ty: &str| val_type(ty).ok_or_else(|| format!("type {ty} cannot be passed to ic0.{name}"));
+    let params = params
+        .iter()
+        .map(|ty| to_wasm(ty))
+        .collect::<Result<Vec<_>, _>>()?;
+    let results = result
+        .map(to_wasm)
+        .transpose()?
+        .into_iter()
+        .collect::<Vec<_>>();
+    if params != api.params || results != api.results {
+        return Err(format!(
+            "ic0.{name} expects ({}) -> ({}), found ({}) -> ({})",
+            pp_types(api.params),
+            pp_types(api.results),
+            pp_types(&params),
+            pp_types(&results)
+        ));
+    }
+    Ok(api)
+}
+
+/// Decode the canister method registered by a wasm export, e.g., `canister_query greet`.
+pub fn decode_export(name: &str) -> Option<String> {
+    if let Some((prefix, method)) = name.split_once(' ') {
+        let mode = match prefix {
+            "canister_query" => "query",
+            "canister_composite_query" => "composite query",
+            "canister_update" => "update",
+            _ => return None,
+        };
+        return Some(format!("registers {mode} method `{method}`"));
+    }
+    let handler = match name {
+        "canister_init" => "init",
+        "canister_pre_upgrade" => "pre_upgrade",
+        "canister_post_upgrade" => "post_upgrade",
+        "canister_inspect_message" => "inspect_message",
+        "canister_heartbeat" => "heartbeat",
+        "canister_global_timer" => "global timer",
+        "canister_on_low_wasm_memory" => "low wasm memory",
+        _ => return None,
+    };
+    Some(format!("registers {handler} handler"))
+}
+/// Decode canister metadata sections, e.g., `icp:public candid:service`.
+pub fn decode_custom_section(name: &str) -> Option<String> {
+    let (visibility, name) = name.split_once(' ')?;
+    match visibility {
+        "icp:public" => Some(format!("public canister metadata `{name}`")),
+        "icp:private" => Some(format!("private canister metadata `{name}`")),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(
+        module: &str,
+        name: &str,
+        params: &[&str],
+        result: Option<&str>,
+    ) -> Result<(), String> {
+        let params: Vec<_> = params.iter().map(|ty| ty.to_string()).collect();
+        check_import(module, name, &params, result).map(|_| ())
+    }
+    #[test]
+    fn unknown_module() {
+        let err = check("env", "debug_print", &["usize", "usize"], None).unwrap_err();
+        assert_eq!(err, "imports from unknown wasm module \"env\"");
+    }
+    #[test]
+    fn wrong_arity() {
+        let err = check("ic0", "debug_print", &["usize"], None).unwrap_err();
+        assert_eq!(
+            err,
+            "ic0.debug_print expects (i32, i32) -> (), found (i32) -> ()"
+        );
+    }
+    #[test]
+    fn usize_is_i32() {
+        assert!(check("ic0", "msg_caller_size", &[], Some("usize")).is_ok());
+        assert!(check("ic0", "msg_caller_copy", &["*mut u8", "usize", "u32"], None).is_ok());
+    }
+    #[test]
+    fn u128_is_rejected() {
+        let err = check("ic0", "mint_cycles", &["u128"], Some("u64")).unwrap_err();
+        assert_eq!(err, "type u128 cannot be passed to ic0.mint_cycles");
+    }
+    #[test]
+    fn stable64_write() {
+        let api = check_import(
+            "ic0",
+            "stable64_write",
+            &["u64".to_string(), "u64".to_string(), "u64".to_string()],
+            None,
+        )
+        .unwrap();
+        assert_eq!(api.contexts, "* s");
+        let err = check("ic0", "stable64_write", &["u32", "u32", "u32"], None).unwrap_err();
+        assert_eq!(
+            err,
+            "ic0.stable64_write expects (i64, i64, i64) -> (), found (i32, i32, i32) -> ()"
+        );
+    }
+}
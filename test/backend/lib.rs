@@ -16,6 +16,10 @@ fn f() -> u8 {
     T::trait_func()
 }
 #[ic_cdk::update]
+fn generic() -> u8 {
+    my_library::generic_trait_func::<T>()
+}
+#[ic_cdk::update]
 fn g() {
     let _ = T + T;
 }
@@ -24,3 +28,95 @@ fn h() -> u64 {
     expand!(my_library::non_ic_func());
     expand!(my_library::stable64_size())
 }
+
+thread_local! {
+    static STATE: std::rc::Rc<std::cell::RefCell<u64>> = Default::default();
+}
+#[ic_cdk::update]
+async fn borrow_across_await() -> u64 {
+    let state = STATE.with(|s| s.clone());
+    let mut guard = state.borrow_mut();
+    *guard += 1;
+    yield_now().await;
+    let count = *guard;
+    drop(guard);
+    let _reader = state.borrow();
+    count
+}
+#[ic_cdk::update]
+async fn borrow_dropped_in_branch(flag: bool) {
+    let state = STATE.with(|s| s.clone());
+    let (guard, _) = (state.borrow_mut(), 0);
+    if flag {
+        drop(guard);
+    }
+    // still held when `flag` is false
+    yield_now().await;
+    let spawned = async {
+        let _reader = state.borrow();
+        yield_now().await;
+    };
+    spawned.await;
+}
+async fn yield_now() {}
+#[ic_cdk::query]
+fn first(v: Vec<u8>) -> u8 {
+    if v.is_empty() {
+        unreachable!("checked by the caller")
+    }
+    v[0]
+}
+#[ic_cdk::query]
+fn now() -> u64 {
+    my_library::timestamp()
+}
+
+const LEDGER: &str = "ryjl3-tyaaa-aaaaa-aaaba-cai";
+#[ic_cdk::update]
+async fn transfer(method: String) {
+    let ledger = candid::Principal::from_text(LEDGER).unwrap();
+    let _: Result<(), _> = ic_cdk::call(ledger, "transfer", ()).await;
+    let _: Result<(), _> = ic_cdk::call(ledger, &method, ()).await;
+}
+#[ic_cdk::update]
+fn visit() -> u8 {
+    let v: Box<dyn my_library::Visitor> = Box::new(T);
+    v.visit()
+}
+#[ic_cdk::update]
+fn schedule() {
+    ic_cdk_timers::set_timer(std::time::Duration::ZERO, tick);
+}
+fn tick() {
+    let _ = my_library::timestamp();
+}
+
+#[ic_cdk::query]
+fn fixture_len() -> usize {
+    read_fixture()
+}
+/// Native integration tests read fixtures from disk
+#[cfg(not(target_arch = "wasm32"))]
+fn read_fixture() -> usize {
+    std::fs::read("fixture.bin").map_or(0, |v| v.len())
+}
+#[cfg(target_arch = "wasm32")]
+fn read_fixture() -> usize {
+    0
+}
+#[ic_cdk::query]
+fn add(a: u64, b: u64) -> u64 {
+    a + b
+}
+#[ic_cdk::update]
+fn hook() -> u8 {
+    my_library::Hook(Box::new(my_library::hooked)).run()
+}
+#[ic_cdk::update]
+fn pause() {
+    std::thread::scope(|_| std::thread::sleep(std::time::Duration::ZERO));
+}
+#[ic_cdk::query]
+fn ratio(a: u64, b: u64) -> u64 {
+    a / b + a / 2 + (a << 3)
+}
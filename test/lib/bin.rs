@@ -0,0 +1,6 @@
+#![no_main]
+
+#[no_mangle]
+pub extern "C" fn canister_query_size() {
+    let _ = unsafe { my_library::stable64_size() };
+}
@@ -3,6 +3,10 @@ pub trait MyTrait {
         42
     }
 }
+pub fn generic_trait_func<X: MyTrait>() -> u8 {
+    // cannot resolve to a concrete impl without --all-impls
+    X::trait_func()
+}
 
 pub struct T;
 impl T {
@@ -19,6 +23,14 @@ impl MyTrait for T {
         expand!(43)
     }
 }
+pub trait Visitor {
+    fn visit(&self) -> u8;
+}
+impl Visitor for T {
+    fn visit(&self) -> u8 {
+        expand!(1)
+    }
+}
 impl From<u8> for T {
     fn from(_: u8) -> T {
         expand!(T)
@@ -46,3 +58,33 @@ extern "C" {
 extern "C" {
     pub fn non_ic_func();
 }
+
+// A dependency can register canister methods and metadata behind our back
+#[export_name = "canister_query injected"]
+extern "C" fn injected() {}
+#[link_section = "icp:public injected"]
+pub static INJECTED: [u8; 3] = *b"abc";
+
+// Global state that is wiped on upgrade
+thread_local! {
+    static COUNTER: std::cell::RefCell<u64> = const { std::cell::RefCell::new(0) };
+}
+pub static mut GLOBAL: u64 = 0;
+
+pub fn timestamp() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// A callback that is only called through `dyn Fn`
+pub struct Hook(pub Box<dyn Fn() -> u8>);
+impl Hook {
+    pub fn run(&self) -> u8 {
+        (self.0)()
+    }
+}
+pub fn hooked() -> u8 {
+    expand!(2)
+}
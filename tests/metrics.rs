@@ -0,0 +1,33 @@
+use std::process::Command;
+
+/// The unsafe blocks of test/lib come from `expand!`, so they are only found after expansion
+#[test]
+fn unsafe_count_includes_macro_expansions() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/test");
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-canister"))
+        .current_dir(dir)
+        .args([
+            "canister",
+            "audit",
+            "-p",
+            "my_library",
+            "--bin",
+            "my_canister",
+        ])
+        .args(["--format", "json", "--no-cache"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let library = report["canisters"][0]["crates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|row| row["crate"] == "my_library")
+        .unwrap();
+    assert!(library["unsafe_functions"].as_u64().unwrap() > 0);
+}
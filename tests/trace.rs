@@ -0,0 +1,114 @@
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// Findings of test/backend traced from its entry points, shared by the tests
+fn entry_point_findings() -> &'static [serde_json::Value] {
+    static FINDINGS: OnceLock<Vec<serde_json::Value>> = OnceLock::new();
+    FINDINGS.get_or_init(|| {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/test");
+        let output = Command::new(env!("CARGO_BIN_EXE_cargo-canister"))
+            .current_dir(dir)
+            // proc macros are expanded with the project's toolchain, not the one running the tests
+            .env_remove("RUSTUP_TOOLCHAIN")
+            .args(["canister", "audit", "-p", "backend", "-t", "--entry-points"])
+            .args(["--format", "json"])
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let mut report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let findings = report["canisters"][0]["findings"].take();
+        serde_json::from_value(findings).unwrap()
+    })
+}
+/// Details of the findings of `kind` reached from `endpoint`
+fn details(kind: &str, endpoint: &str) -> Vec<String> {
+    entry_point_findings()
+        .iter()
+        .filter(|f| f["kind"] == kind && f["endpoint"] == endpoint)
+        .map(|f| f["detail"].as_str().unwrap_or_default().to_string())
+        .collect()
+}
+
+/// `hook` in test/backend only reaches `my_library::hooked` through a `Box<dyn Fn>`
+#[test]
+fn function_in_box_dyn_fn_is_traced() {
+    let found = entry_point_findings().iter().any(|f| {
+        f["kind"] == "unsafe"
+            && f["endpoint"] == "update `hook`"
+            && f["function"] == "my_library::hooked"
+    });
+    assert!(found);
+}
+
+/// The guards are held across the first `.await`, but not by the borrow in the async block
+#[test]
+fn borrow_across_await_is_reported() {
+    assert_eq!(
+        details("borrow-across-await", "update `borrow_across_await`"),
+        ["`guard: RefMut<u64>` across .await at line 40"]
+    );
+    assert_eq!(
+        details("borrow-across-await", "update `borrow_dropped_in_branch`"),
+        ["`guard: RefMut<u64>` across .await at line 54"]
+    );
+}
+
+#[test]
+fn panics_are_reported() {
+    let first = details("panic", "query `first`");
+    assert!(first.contains(&"`unreachable!`".to_string()), "{first:?}");
+    assert!(
+        first.contains(&"`[]` on `Vec<u8, Global>`".to_string()),
+        "{first:?}"
+    );
+    // test/Cargo.toml enables overflow checks in the release profile
+    assert_eq!(details("panic", "query `add`"), ["`+` on `u64`"]);
+    // `a / 2` and `a << 3` cannot panic
+    let ratio = details("panic", "query `ratio`");
+    assert_eq!(ratio.iter().filter(|d| *d == "`/` on `u64`").count(), 1);
+    assert!(!ratio.iter().any(|d| d.contains("<<")), "{ratio:?}");
+}
+
+#[test]
+fn unsupported_apis_are_reported() {
+    assert_eq!(
+        details("unsupported-api", "query `now`"),
+        ["`std::time::SystemTime::now` (non-deterministic, use ic_cdk::api::time)"]
+    );
+    assert_eq!(
+        details("unsupported-api", "update `pause`"),
+        [
+            "`std::thread::scoped::scope` (threads are not supported)",
+            "`std::thread::functions::sleep` (blocking is not supported, use timers)"
+        ]
+    );
+}
+
+#[test]
+fn canister_call_is_reported() {
+    let calls = details("canister-call", "update `transfer`");
+    let expected = "`call` to `ryjl3-tyaaa-aaaaa-aaaba-cai`, method `transfer`".to_string();
+    assert!(calls.contains(&expected), "{calls:?}");
+}
+
+/// `generic_trait_func::<X>` calls `X::trait_func`, whose impl is unknown without `--all-impls`
+#[test]
+fn trait_call_on_type_parameter_is_warned() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/test");
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-canister"))
+        .current_dir(dir)
+        .env_remove("RUSTUP_TOOLCHAIN")
+        .args(["canister", "audit", "-p", "backend", "-t", "--entry-points"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains("calls my_library::MyTrait::trait_func on type parameter `X`"),
+        "{stderr}"
+    );
+}